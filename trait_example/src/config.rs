@@ -0,0 +1,659 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+    str::FromStr,
+    sync::Arc,
+};
+
+use serde::{de::Error as _, Deserialize, Deserializer};
+use serde_json::Value;
+use sqlx::{
+    postgres::PgPoolOptions,
+    sqlite::{SqliteConnectOptions, SqlitePoolOptions},
+    Pool, Sqlite,
+};
+
+use crate::errors::Result;
+use crate::files::FileOptions;
+use crate::formats::{CsvSinkOptions, ParquetSinkOptions};
+use crate::jobs::Job;
+use crate::partitioning::PartitionMode;
+use crate::multi_source::{JoinKind, MultiSource};
+use crate::pipelines::{SinkPolicy, StreamOptions};
+use crate::quality::CheckConfig;
+use crate::registry::Registry;
+use crate::schema::ContractConfig;
+use crate::sinks::{ChangeDetection, DeletePropagation, PostgresTableOptions, SharedSink, Sinker};
+use crate::sources::{SharedSource, SourceKind};
+use crate::sql::SqlTransform;
+use crate::sqlite::SqliteWriteMode;
+
+// ============================================================================
+// Job config
+// ============================================================================
+
+/// A job described in a JSON file.
+///
+/// ```json
+/// {
+///   "name": "confluence_pages",
+///   "source": { "kind": "http", "url": "https://example.com/api/pages" },
+///   "stages": [
+///     { "kind": "check", "rule": "not_null", "column": "id", "severity": "quarantine" },
+///     { "kind": "sql", "query": "SELECT id, title FROM input" }
+///   ],
+///   "sink": { "kind": "parquet", "path": "out/{run_date}/pages_{run_id}.parquet", "retention_days": 30 },
+///   "params": { "space": "DOCS" }
+/// }
+/// ```
+///
+/// Kinds other than the built-in ones are looked up in a [`Registry`] by
+/// [`into_job_with`](Self::into_job_with).
+#[derive(Clone, Debug, Deserialize)]
+pub struct JobConfig {
+    pub name: String,
+    pub source: SourceSpec,
+    pub sink: SinkSpec,
+    /// Further sinks the same output is written to.
+    #[serde(default)]
+    pub sinks: Vec<SinkSpec>,
+    #[serde(default)]
+    pub sink_policy: SinkPolicy,
+    #[serde(default)]
+    pub stages: Vec<StageConfig>,
+    pub quarantine: Option<SinkSpec>,
+    pub contract: Option<ContractConfig>,
+    pub history: Option<String>,
+    pub streaming: Option<StreamingConfig>,
+    /// Values for `{param.<key>}` in path and metadata templates.
+    #[serde(default)]
+    pub params: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct StreamingConfig {
+    pub batch_size: usize,
+    pub channel_capacity: Option<usize>,
+}
+
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SourceConfig {
+    Http {
+        url: String,
+        #[serde(default)]
+        headers: HashMap<String, String>,
+        #[serde(default)]
+        query: HashMap<String, String>,
+        bearer_token: Option<String>,
+        basic_auth: Option<(String, String)>,
+        #[serde(default)]
+        retries: u32,
+        /// Field of an object response holding the records, e.g. `"results"`.
+        records_field: Option<String>,
+        pagination: Option<PaginationConfig>,
+    },
+    Csv {
+        path: String,
+    },
+    Parquet {
+        path: String,
+    },
+    #[serde(rename = "ndjson", alias = "nd_json")]
+    NdJson {
+        path: String,
+    },
+    Sqlite {
+        /// e.g. `sqlite://data/app.db`
+        url: String,
+        query: String,
+    },
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct PaginationConfig {
+    pub offset_param: String,
+    pub limit_param: String,
+    pub page_size: usize,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SinkConfig {
+    Csv {
+        path: String,
+        #[serde(default)]
+        options: CsvSinkOptions,
+        #[serde(flatten)]
+        file: FileOptions,
+    },
+    Parquet {
+        path: String,
+        #[serde(default)]
+        options: ParquetSinkOptions,
+        #[serde(flatten)]
+        file: FileOptions,
+    },
+    Json {
+        path: String,
+        #[serde(flatten)]
+        file: FileOptions,
+    },
+    #[serde(rename = "ndjson", alias = "nd_json")]
+    NdJson {
+        path: String,
+        #[serde(flatten)]
+        file: FileOptions,
+    },
+    Ipc {
+        path: String,
+        #[serde(flatten)]
+        file: FileOptions,
+    },
+    PartitionedParquet {
+        path: String,
+        partition_by: Vec<String>,
+        max_rows_per_file: Option<usize>,
+        #[serde(default)]
+        mode: PartitionMode,
+        #[serde(default)]
+        options: ParquetSinkOptions,
+        #[serde(flatten)]
+        file: FileOptions,
+    },
+    /// The pool is created lazily, so no connection is made until the sink runs.
+    Postgres {
+        url: String,
+        schema: String,
+        table: String,
+        #[serde(default)]
+        auto_create: bool,
+        #[serde(default)]
+        upsert: bool,
+        primary_key: Option<String>,
+        /// Column types; the rest is used when `auto_create` is set.
+        #[serde(default)]
+        table_options: PostgresTableOptions,
+        /// Serialize concurrent writers to the table.
+        #[serde(default)]
+        advisory_lock: bool,
+        /// `"columns"` or `{ "hash": "row_hash" }` to skip unchanged rows on upsert.
+        #[serde(default)]
+        change_detection: ChangeDetection,
+        /// Delete target rows missing from the batch; needs `upsert` and no `streaming`.
+        deletes: Option<DeletePropagation>,
+    },
+    /// The database file is created if missing.
+    Sqlite {
+        url: String,
+        table: String,
+        #[serde(default)]
+        auto_create: bool,
+        #[serde(default)]
+        mode: SqliteWriteMode,
+        primary_key: Option<String>,
+    },
+}
+
+/// A source entry: a built-in kind, or a custom kind with its parameters.
+#[derive(Clone, Debug)]
+pub enum SourceSpec {
+    Builtin(SourceConfig),
+    Multi(MultiSourceConfig),
+    Custom { kind: String, params: Value },
+}
+
+/// A sink entry: a built-in kind, or a custom kind with its parameters.
+#[derive(Clone, Debug)]
+pub enum SinkSpec {
+    Builtin(SinkConfig),
+    Custom { kind: String, params: Value },
+}
+
+impl SourceConfig {
+    const KINDS: &'static [&'static str] =
+        &["http", "csv", "parquet", "ndjson", "nd_json", "sqlite"];
+}
+
+impl SinkConfig {
+    const KINDS: &'static [&'static str] = &[
+        "csv",
+        "parquet",
+        "json",
+        "ndjson",
+        "nd_json",
+        "ipc",
+        "partitioned_parquet",
+        "postgres",
+        "sqlite",
+    ];
+}
+
+/// Read a `kind`-tagged object, returning the kind and the whole object.
+fn tagged<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<(String, Value), D::Error> {
+    let value = Value::deserialize(deserializer)?;
+    let kind = value
+        .get("kind")
+        .and_then(Value::as_str)
+        .ok_or_else(|| D::Error::missing_field("kind"))?
+        .to_string();
+    Ok((kind, value))
+}
+
+/// Parameters handed to a registry factory: the object without its `kind`.
+fn params(mut value: Value) -> Value {
+    if let Some(object) = value.as_object_mut() {
+        object.remove("kind");
+    }
+    value
+}
+
+impl<'de> Deserialize<'de> for SourceSpec {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let (kind, value) = tagged(deserializer)?;
+        Ok(if SourceConfig::KINDS.contains(&kind.as_str()) {
+            SourceSpec::Builtin(serde_json::from_value(value).map_err(D::Error::custom)?)
+        } else if MultiSourceConfig::KINDS.contains(&kind.as_str()) {
+            SourceSpec::Multi(serde_json::from_value(value).map_err(D::Error::custom)?)
+        } else {
+            SourceSpec::Custom {
+                kind,
+                params: params(value),
+            }
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for SinkSpec {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let (kind, value) = tagged(deserializer)?;
+        Ok(if SinkConfig::KINDS.contains(&kind.as_str()) {
+            SinkSpec::Builtin(serde_json::from_value(value).map_err(D::Error::custom)?)
+        } else {
+            SinkSpec::Custom {
+                kind,
+                params: params(value),
+            }
+        })
+    }
+}
+
+/// Several sources combined into one, see [`MultiSource`].
+///
+/// ```json
+/// {
+///   "kind": "join", "on": ["id"], "how": "left",
+///   "sources": [
+///     { "name": "pages", "source": { "kind": "http", "url": "https://a.example/pages" } },
+///     { "name": "users", "source": { "kind": "csv", "path": "users.csv" } }
+///   ]
+/// }
+/// ```
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum MultiSourceConfig {
+    Join {
+        on: Vec<String>,
+        #[serde(default)]
+        how: JoinKind,
+        sources: Vec<NamedSourceSpec>,
+    },
+    Union {
+        /// Column recording each row's source name.
+        source_column: Option<String>,
+        sources: Vec<NamedSourceSpec>,
+    },
+}
+
+impl MultiSourceConfig {
+    const KINDS: &'static [&'static str] = &["join", "union"];
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct NamedSourceSpec {
+    pub name: String,
+    pub source: SourceSpec,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum StageConfig {
+    /// A data quality check; see [`CheckConfig`].
+    Check(CheckConfig),
+    Sql {
+        query: String,
+        table_name: Option<String>,
+        /// Extra sources registered as tables, keyed by table name.
+        #[serde(default)]
+        sources: HashMap<String, SourceSpec>,
+    },
+}
+
+impl JobConfig {
+    pub fn from_json(json: &str) -> Result<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_json(&std::fs::read_to_string(path)?)
+    }
+
+    /// Build the job; fails on kinds that are not built in.
+    pub fn into_job(self) -> Result<Job<'static>> {
+        self.into_job_with(&Registry::default())
+    }
+
+    /// Build the job, resolving custom source and sink kinds in `registry`.
+    pub fn into_job_with(self, registry: &Registry) -> Result<Job<'static>> {
+        let mut job = Job::from_shared(
+            self.name,
+            self.source.into_shared(registry)?,
+            self.sink.into_shared(registry)?,
+        )
+        .with_sink_policy(self.sink_policy);
+        for (key, value) in self.params {
+            job = job.with_param(key, value);
+        }
+        for sink in self.sinks {
+            job = job.with_shared_sink(sink.into_shared(registry)?);
+        }
+
+        for stage in self.stages {
+            job = match stage {
+                StageConfig::Check(check) => job.with_check(check.into_check()?),
+                StageConfig::Sql {
+                    query,
+                    table_name,
+                    sources,
+                } => {
+                    let mut transform = SqlTransform::new(query);
+                    if let Some(name) = table_name {
+                        transform = transform.table_name(name);
+                    }
+                    for (name, source) in sources {
+                        transform = transform.shared_source(name, source.into_shared(registry)?);
+                    }
+                    job.with_sql(transform)
+                }
+            };
+        }
+
+        if let Some(quarantine) = self.quarantine {
+            job = job.with_shared_quarantine(quarantine.into_shared(registry)?);
+        }
+        if let Some(contract) = self.contract {
+            job = job.with_contract(contract.into_contract()?);
+        }
+        if let Some(history) = self.history {
+            job = job.with_history(history);
+        }
+        if let Some(streaming) = self.streaming {
+            let defaults = StreamOptions::default();
+            job = job.with_streaming(StreamOptions {
+                batch_size: streaming.batch_size.max(1),
+                channel_capacity: streaming
+                    .channel_capacity
+                    .unwrap_or(defaults.channel_capacity)
+                    .max(1),
+            });
+        }
+        Ok(job)
+    }
+}
+
+impl SourceSpec {
+    pub fn into_shared(self, registry: &Registry) -> Result<SharedSource<'static>> {
+        match self {
+            SourceSpec::Builtin(config) => Ok(Arc::new(config.into_source()?)),
+            SourceSpec::Multi(config) => {
+                let (mut multi, sources) = match config {
+                    MultiSourceConfig::Join { on, how, sources } => (MultiSource::join(on, how), sources),
+                    MultiSourceConfig::Union {
+                        source_column,
+                        sources,
+                    } => {
+                        let mut multi = MultiSource::union();
+                        if let Some(column) = source_column {
+                            multi = multi.with_source_column(column);
+                        }
+                        (multi, sources)
+                    }
+                };
+                for named in sources {
+                    multi = multi.shared_source(named.name, named.source.into_shared(registry)?);
+                }
+                Ok(Arc::new(multi))
+            }
+            SourceSpec::Custom { kind, params } => registry.build_source(&kind, params),
+        }
+    }
+}
+
+impl SinkSpec {
+    pub fn into_shared(self, registry: &Registry) -> Result<SharedSink<'static>> {
+        match self {
+            SinkSpec::Builtin(config) => Ok(Arc::new(config.into_sinker()?)),
+            SinkSpec::Custom { kind, params } => registry.build_sink(&kind, params),
+        }
+    }
+}
+
+impl SourceConfig {
+    pub fn into_source(self) -> Result<SourceKind<'static>> {
+        Ok(match self {
+            SourceConfig::Http {
+                url,
+                headers,
+                query,
+                bearer_token,
+                basic_auth,
+                retries,
+                records_field,
+                pagination,
+            } => {
+                let mut builder = SourceKind::http(url).retries(retries);
+                if let Some(field) = records_field {
+                    builder = builder.records_field(field);
+                }
+                if let Some(p) = pagination {
+                    builder = builder.paginate(p.offset_param, p.limit_param, p.page_size);
+                }
+                for (k, v) in headers {
+                    builder = builder.header(k, v);
+                }
+                for (k, v) in query {
+                    builder = builder.query(k, v);
+                }
+                if let Some(token) = bearer_token {
+                    builder = builder.bearer(token);
+                }
+                if let Some((user, pass)) = basic_auth {
+                    builder = builder.basic(user, pass);
+                }
+                builder.build()
+            }
+            SourceConfig::Csv { path } => SourceKind::read_csv(path),
+            SourceConfig::Parquet { path } => SourceKind::read_parquet(path),
+            SourceConfig::NdJson { path } => SourceKind::read_ndjson(path),
+            SourceConfig::Sqlite { url, query } => {
+                SourceKind::read_sqlite(Arc::new(sqlite_pool(&url)?), query)
+            }
+        })
+    }
+}
+
+impl SinkConfig {
+    pub fn into_sinker(self) -> Result<Sinker<'static>> {
+        Ok(match self {
+            SinkConfig::Csv {
+                path,
+                options,
+                file,
+            } => Sinker::csv(path)
+                .with_csv_options(options)
+                .with_file_options(file),
+            SinkConfig::Parquet {
+                path,
+                options,
+                file,
+            } => Sinker::parquet(path)
+                .with_parquet_options(options)
+                .with_file_options(file),
+            SinkConfig::Json { path, file } => Sinker::json(path).with_file_options(file),
+            SinkConfig::NdJson { path, file } => Sinker::ndjson(path).with_file_options(file),
+            SinkConfig::Ipc { path, file } => Sinker::ipc(path).with_file_options(file),
+            SinkConfig::PartitionedParquet {
+                path,
+                partition_by,
+                max_rows_per_file,
+                mode,
+                options,
+                file,
+            } => {
+                let mut sinker = Sinker::partitioned_parquet(path, partition_by)
+                    .with_partition_mode(mode)
+                    .with_parquet_options(options)
+                    .with_file_options(file);
+                if let Some(rows) = max_rows_per_file {
+                    sinker = sinker.with_max_rows_per_file(rows);
+                }
+                sinker
+            }
+            SinkConfig::Postgres {
+                url,
+                schema,
+                table,
+                auto_create,
+                upsert,
+                primary_key,
+                table_options,
+                advisory_lock,
+                change_detection,
+                deletes,
+            } => {
+                let pool = PgPoolOptions::new().connect_lazy(&url)?;
+                let sinker = Sinker::postgres(
+                    Arc::new(pool),
+                    schema,
+                    table,
+                    auto_create,
+                    upsert,
+                    primary_key.map(Into::into),
+                )
+                .with_table_options(table_options)
+                .with_change_detection(change_detection);
+                let sinker = match deletes {
+                    Some(deletes) => sinker.with_delete_propagation(deletes),
+                    None => sinker,
+                };
+                if advisory_lock {
+                    sinker.with_advisory_lock()
+                } else {
+                    sinker
+                }
+            }
+            SinkConfig::Sqlite {
+                url,
+                table,
+                auto_create,
+                mode,
+                primary_key,
+            } => {
+                let sinker = Sinker::sqlite(Arc::new(sqlite_pool(&url)?), table, auto_create, mode);
+                match primary_key {
+                    Some(pk) => sinker.with_primary_key(pk),
+                    None => sinker,
+                }
+            }
+        })
+    }
+}
+
+/// A lazily connecting pool that creates the database file if missing.
+fn sqlite_pool(url: &str) -> Result<Pool<Sqlite>> {
+    let options = SqliteConnectOptions::from_str(url)?.create_if_missing(true);
+    Ok(SqlitePoolOptions::new().connect_lazy_with(options))
+}
+
+#[cfg(test)]
+mod tests {
+    use polars::prelude::*;
+
+    use super::*;
+    use crate::quality::Severity;
+    use crate::sinks::Sink;
+    use crate::sources::Source;
+
+    #[test]
+    fn check_stage_from_json() {
+        let stage: StageConfig = serde_json::from_str(
+            r#"{ "kind": "check", "rule": "not_null", "column": "id",
+                 "severity": "quarantine", "name": "ids" }"#,
+        )
+        .unwrap();
+        let StageConfig::Check(config) = stage else {
+            panic!("expected a check stage");
+        };
+        let check = config.into_check().unwrap();
+        assert_eq!(check.name(), "ids");
+        assert_eq!(check.severity(), Severity::Quarantine);
+
+        let df = df!("id" => [Some(1), None, Some(3)]).unwrap();
+        let result = check.apply(df).unwrap();
+        assert_eq!(result.df.height(), 2);
+        assert_eq!(result.report.quarantined_rows, 1);
+    }
+
+    #[test]
+    fn check_severity_defaults_to_fail() {
+        let stage: StageConfig =
+            serde_json::from_str(r#"{ "kind": "check", "rule": "cast", "column": "n", "dtype": "int64" }"#)
+                .unwrap();
+        let StageConfig::Check(config) = stage else {
+            panic!("expected a check stage");
+        };
+        let check = config.into_check().unwrap();
+        assert_eq!(check.severity(), Severity::Fail);
+        assert!(check.apply(df!("n" => ["1", "x"]).unwrap()).is_err());
+    }
+
+    /// The variants serde accepts for a `kind`-tagged enum, read from the
+    /// error for an unknown kind.
+    fn serde_kinds<T: serde::de::DeserializeOwned + std::fmt::Debug>() -> Vec<String> {
+        let err = serde_json::from_str::<T>(r#"{ "kind": "?" }"#).unwrap_err().to_string();
+        let (_, expected) = err.split_once("expected").expect(&err);
+        // Names are quoted in backticks: "`a` or `b`", "one of `a`, `b`, `c`".
+        let mut kinds: Vec<_> = expected.split('`').skip(1).step_by(2).map(String::from).collect();
+        kinds.sort();
+        kinds
+    }
+
+    fn sorted(kinds: &[&str]) -> Vec<String> {
+        let mut kinds: Vec<_> = kinds.iter().map(|k| k.to_string()).collect();
+        kinds.sort();
+        kinds
+    }
+
+    #[test]
+    fn kinds_list_every_builtin_variant() {
+        assert_eq!(serde_kinds::<SourceConfig>(), sorted(SourceConfig::KINDS));
+        assert_eq!(serde_kinds::<SinkConfig>(), sorted(SinkConfig::KINDS));
+        assert_eq!(serde_kinds::<MultiSourceConfig>(), sorted(MultiSourceConfig::KINDS));
+    }
+
+    #[test]
+    fn ndjson_kind_matches_its_label() {
+        for kind in ["ndjson", "nd_json"] {
+            let json = format!(r#"{{ "kind": "{kind}", "path": "out.ndjson" }}"#);
+            let SourceSpec::Builtin(source) = serde_json::from_str(&json).unwrap() else {
+                panic!("{kind} should be a built-in source");
+            };
+            assert_eq!(source.into_source().unwrap().kind(), "ndjson");
+            let SinkSpec::Builtin(sink) = serde_json::from_str(&json).unwrap() else {
+                panic!("{kind} should be a built-in sink");
+            };
+            assert_eq!(sink.into_sinker().unwrap().kind(), "ndjson");
+        }
+    }
+}
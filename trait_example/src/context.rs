@@ -0,0 +1,156 @@
+use std::{collections::BTreeMap, fmt::Write as _, future::Future};
+
+use chrono::{DateTime, Utc};
+
+use crate::errors::{Error, Result};
+use crate::reports::RunReport;
+
+tokio::task_local! {
+    static RUN: RunContext;
+}
+
+/// The run a sink is writing for, available to sinks through
+/// [`RunContext::current`] while `Pipeline::run` is executing.
+#[derive(Clone, Debug)]
+pub struct RunContext {
+    pub job: String,
+    pub run_id: String,
+    pub started_at_ms: u64,
+    /// Job parameters, e.g. from the `params` object of a job file.
+    pub params: BTreeMap<String, String>,
+    /// High-water marks of incremental loads, set by the caller per run.
+    pub watermarks: BTreeMap<String, String>,
+}
+
+impl RunContext {
+    pub(crate) fn from_report(report: &RunReport) -> Self {
+        Self {
+            job: report.pipeline.clone(),
+            run_id: report.run_id.clone(),
+            started_at_ms: report.started_at_ms,
+            params: BTreeMap::new(),
+            watermarks: BTreeMap::new(),
+        }
+    }
+
+    /// The current run, or `None` outside a pipeline run.
+    pub fn current() -> Option<Self> {
+        RUN.try_with(Clone::clone).ok()
+    }
+
+    pub(crate) async fn scope<F: Future>(self, f: F) -> F::Output {
+        RUN.scope(self, f).await
+    }
+
+    /// Fill in the placeholders of `template`, written `{name}` or `{{name}}`:
+    ///
+    /// - `job`, `run_id`
+    /// - `run_date` (`2025-01-31`) and `run_ts` (`20250131T060000Z`), both UTC;
+    ///   either takes a strftime format, e.g. `{run_ts:%Y/%m/%d/%H}`
+    /// - `param.<name>` and `watermark.<name>`
+    ///
+    /// Unknown names are an error rather than being left in the output.
+    pub fn render(&self, template: &str) -> Result<String> {
+        let mut out = String::with_capacity(template.len());
+        for piece in parse(template)? {
+            match piece {
+                Piece::Text(text) => out.push_str(text),
+                Piece::Var(var) => self.push_var(&mut out, var, template)?,
+            }
+        }
+        Ok(out)
+    }
+
+    fn push_var(&self, out: &mut String, var: &str, template: &str) -> Result<()> {
+        let (name, format) = match var.split_once(':') {
+            Some((name, format)) => (name, Some(format)),
+            None => (var, None),
+        };
+        let unknown = || Error::Config(format!("unknown placeholder '{{{var}}}' in '{template}'"));
+        match name {
+            "job" => out.push_str(&self.job),
+            "run_id" => out.push_str(&self.run_id),
+            "run_date" | "run_ts" => {
+                let default = if name == "run_date" { "%Y-%m-%d" } else { "%Y%m%dT%H%M%SZ" };
+                let started: DateTime<Utc> =
+                    DateTime::from_timestamp_millis(self.started_at_ms as i64).unwrap_or_default();
+                write!(out, "{}", started.format(format.unwrap_or(default))).map_err(|_| {
+                    Error::Config(format!("invalid time format in '{{{var}}}' in '{template}'"))
+                })?;
+            }
+            _ => {
+                let value = if let Some(key) = name.strip_prefix("param.") {
+                    self.params.get(key)
+                } else if let Some(key) = name.strip_prefix("watermark.") {
+                    self.watermarks.get(key)
+                } else {
+                    None
+                };
+                out.push_str(value.ok_or_else(unknown)?);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Render `template` against the current run. Outside a run only templates
+/// without placeholders are accepted.
+pub fn expand(template: &str) -> Result<String> {
+    match RunContext::current() {
+        Some(run) => run.render(template),
+        None if has_placeholders(template) => Err(Error::Config(format!(
+            "'{template}' has placeholders but no pipeline run is active"
+        ))),
+        None => Ok(template.to_string()),
+    }
+}
+
+/// Whether `template` has a `{...}` placeholder. An unclosed `{` counts,
+/// since rendering it fails.
+pub fn has_placeholders(template: &str) -> bool {
+    parse(template).map_or(true, |pieces| pieces.iter().any(|p| matches!(p, Piece::Var(_))))
+}
+
+pub(crate) enum Piece<'t> {
+    Text(&'t str),
+    /// Placeholder name, with any `:format` suffix.
+    Var(&'t str),
+}
+
+/// Split `template` into literal text and placeholders.
+pub(crate) fn parse(template: &str) -> Result<Vec<Piece<'_>>> {
+    let mut pieces = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        if start > 0 {
+            pieces.push(Piece::Text(&rest[..start]));
+        }
+        let double = rest[start..].starts_with("{{");
+        let (open, close) = if double { ("{{", "}}") } else { ("{", "}") };
+        let body = &rest[start + open.len()..];
+        let end = body
+            .find(close)
+            .ok_or_else(|| Error::Config(format!("unclosed placeholder in '{template}'")))?;
+        pieces.push(Piece::Var(body[..end].trim()));
+        rest = &body[end + close.len()..];
+    }
+    if !rest.is_empty() {
+        pieces.push(Piece::Text(rest));
+    }
+    Ok(pieces)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn has_placeholders_follows_the_parser() {
+        assert!(has_placeholders("out/{job}.csv"));
+        assert!(has_placeholders("out/{{ run_date }}.csv"));
+        assert!(has_placeholders("out/{job.csv"));
+        assert!(!has_placeholders("out/job.csv"));
+        assert!(expand("out/job.csv").is_ok());
+        assert!(expand("out/{job}.csv").is_err());
+    }
+}
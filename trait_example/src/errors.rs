@@ -10,6 +10,12 @@ pub enum Error {
     SerdeJson(String),
     Sqlx(String),
     JoinError(String),
+    Prometheus(String),
+    Telemetry(String),
+    Quality(String),
+    Config(String),
+    Cancelled(String),
+    Schema(String),
 }
 
 impl core::fmt::Display for Error {
@@ -29,4 +35,5 @@ crate::impl_from_error!(
     serde_json::Error => SerdeJson,
     sqlx::Error => Sqlx,
     tokio::task::JoinError => JoinError,
+    prometheus::Error => Prometheus,
 );
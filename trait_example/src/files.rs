@@ -0,0 +1,442 @@
+use std::{
+    collections::BTreeSet,
+    fs::{self, File, OpenOptions},
+    io::{self, Read},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use regex::Regex;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tracing::info;
+
+use crate::context::{self, Piece};
+use crate::errors::{Error, Result};
+
+/// Infix of [`AtomicFile`] temporary names, `<file>.tmp-<uuid>`.
+const TMP_MARKER: &str = ".tmp-";
+
+/// Extras written next to a file once it is complete.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+pub struct FileOptions {
+    /// Touch `_SUCCESS` in the file's directory after the file is in place.
+    #[serde(default)]
+    pub success_marker: bool,
+    /// Write `<file>.sha256` in `sha256sum` format.
+    #[serde(default)]
+    pub checksum: bool,
+    /// After a successful write, delete earlier outputs of a templated path
+    /// that are older than this many days; see [`apply_retention`].
+    #[serde(default)]
+    pub retention_days: Option<u32>,
+}
+
+/// A file written under a temporary sibling name and renamed into place on
+/// [`commit`](Self::commit), so readers never see a partial file. Dropping it
+/// without committing removes the temporary file.
+pub struct AtomicFile {
+    target: PathBuf,
+    tmp: PathBuf,
+    file: Option<File>,
+    /// Set once the rename succeeded; until then `Drop` removes `tmp`.
+    committed: bool,
+}
+
+impl AtomicFile {
+    pub fn create(target: impl AsRef<Path>) -> Result<Self> {
+        let target = target.as_ref().to_path_buf();
+        let tmp = sibling(&target, &format!("{TMP_MARKER}{}", uuid::Uuid::new_v4()));
+        let file = File::create(&tmp)?;
+        Ok(Self {
+            target,
+            tmp,
+            file: Some(file),
+            committed: false,
+        })
+    }
+
+    pub fn file(&mut self) -> &mut File {
+        self.file.as_mut().expect("file is open until commit")
+    }
+
+    /// Fsync, rename over the target, then write the checksum and marker.
+    pub fn commit(mut self, options: FileOptions) -> Result<()> {
+        if let Some(file) = self.file.take() {
+            file.sync_all()?;
+        }
+        let checksum = if options.checksum {
+            Some(sha256_file(&self.tmp)?)
+        } else {
+            None
+        };
+
+        fs::rename(&self.tmp, &self.target)?;
+        self.committed = true;
+        sync_dir(&self.target)?;
+        write_extras(&self.target, checksum, options)
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = fs::remove_file(&self.tmp);
+        }
+    }
+}
+
+/// A file that is either replaced atomically or appended to in place.
+///
+/// Appends cannot be made atomic by renaming, so they are fsynced instead;
+/// a crash mid-append can leave a partial last record.
+pub enum OutputFile {
+    Replace(AtomicFile),
+    Append {
+        path: PathBuf,
+        file: File,
+        was_empty: bool,
+    },
+}
+
+impl OutputFile {
+    pub fn open(path: impl AsRef<Path>, append: bool) -> Result<Self> {
+        if !append {
+            return Ok(OutputFile::Replace(AtomicFile::create(path)?));
+        }
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let was_empty = file.metadata()?.len() == 0;
+        Ok(OutputFile::Append {
+            path,
+            file,
+            was_empty,
+        })
+    }
+
+    pub fn file(&mut self) -> &mut File {
+        match self {
+            OutputFile::Replace(atomic) => atomic.file(),
+            OutputFile::Append { file, .. } => file,
+        }
+    }
+
+    /// True unless appending to a file that already has content.
+    pub fn is_new(&self) -> bool {
+        match self {
+            OutputFile::Replace(_) => true,
+            OutputFile::Append { was_empty, .. } => *was_empty,
+        }
+    }
+
+    /// Make the write durable; the checksum covers the whole file.
+    pub fn commit(self, options: FileOptions) -> Result<()> {
+        match self {
+            OutputFile::Replace(atomic) => atomic.commit(options),
+            OutputFile::Append { path, file, .. } => {
+                file.sync_all()?;
+                let checksum = if options.checksum {
+                    Some(sha256_file(&path)?)
+                } else {
+                    None
+                };
+                write_extras(&path, checksum, options)
+            }
+        }
+    }
+}
+
+/// Checksum sidecar and `_SUCCESS` marker for a file that is in place.
+fn write_extras(target: &Path, checksum: Option<String>, options: FileOptions) -> Result<()> {
+    if let Some(digest) = checksum {
+        let name = target
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        write_small(&sibling(target, ".sha256"), format!("{digest}  {name}\n").as_bytes())?;
+    }
+    if options.success_marker {
+        write_small(&parent(target).join("_SUCCESS"), b"")?;
+    }
+    Ok(())
+}
+
+/// Render a path template for the current run and create its parent
+/// directories.
+pub fn output_path(template: &str) -> Result<PathBuf> {
+    let path = PathBuf::from(context::expand(template)?);
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => fs::create_dir_all(dir)?,
+        _ => {}
+    }
+    Ok(path)
+}
+
+/// Delete outputs of earlier runs of a templated path: files below the
+/// template's fixed directory that match it and were last modified more than
+/// `days` days ago. A placeholder matches one path segment, or as many as its
+/// time format spans (`{run_ts:%Y/%m/%d}` matches three). With `nested`, the
+/// template names a directory and everything under it matches; otherwise the
+/// search stops at the template's own depth.
+///
+/// Checksum sidecars go with their file, and directories left empty (or
+/// holding only `_SUCCESS`) are removed. A path without placeholders has no
+/// earlier outputs, so nothing is deleted. Templates without a fixed parent
+/// directory, e.g. `{job}.csv`, `./{job}.csv` or `/{job}/out.csv`, are refused rather than
+/// searching the working directory or the whole filesystem. Returns the
+/// number of files deleted.
+pub fn apply_retention(template: &str, days: u32, nested: bool) -> Result<usize> {
+    let pieces = context::parse(template)?;
+    let Some(first_var) = pieces.iter().position(|p| matches!(p, Piece::Var(_))) else {
+        return Ok(0);
+    };
+    let prefix: String = pieces[..first_var]
+        .iter()
+        .filter_map(|p| match p {
+            Piece::Text(text) => Some(*text),
+            Piece::Var(_) => None,
+        })
+        .collect();
+    let split = match prefix.rfind('/') {
+        Some(i) if i > 0 && &prefix[..i] != "." => i,
+        _ => {
+            return Err(Error::Config(format!(
+                "retention needs a fixed parent directory before the first placeholder in '{template}'"
+            )));
+        }
+    };
+    let root = PathBuf::from(&prefix[..split]);
+
+    let mut pattern = String::from("^");
+    let mut depth = 0;
+    for piece in &pieces {
+        match piece {
+            Piece::Text(text) => {
+                pattern.push_str(&regex::escape(text));
+                depth += text.matches('/').count();
+            }
+            Piece::Var(var) => {
+                let slashes = var.split_once(':').map_or(0, |(_, f)| f.matches('/').count());
+                pattern.push_str("[^/]+");
+                pattern.push_str(&"/[^/]+".repeat(slashes));
+                depth += slashes;
+            }
+        }
+    }
+    if nested {
+        pattern.push_str("/.+");
+    }
+    pattern.push('$');
+    let pattern = Regex::new(&pattern).map_err(|e| Error::Config(e.to_string()))?;
+
+    // Levels between the root and the template's files.
+    let depth = depth - prefix[..=split].matches('/').count();
+    let cutoff = SystemTime::now() - Duration::from_secs(u64::from(days) * 86_400);
+    let mut files = Vec::new();
+    if root.is_dir() {
+        walk(&root, (!nested).then_some(depth), &mut files)?;
+    }
+    let mut deleted = 0;
+    let mut dirs = BTreeSet::new();
+    for file in files {
+        if !pattern.is_match(&file.to_string_lossy()) {
+            continue;
+        }
+        // A file removed since the walk, e.g. by a concurrent run, is gone
+        // either way.
+        let modified = match fs::metadata(&file).and_then(|m| m.modified()) {
+            Ok(modified) => modified,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+        if modified >= cutoff {
+            continue;
+        }
+        if remove_if_exists(&file)? {
+            deleted += 1;
+        }
+        remove_if_exists(&sibling(&file, ".sha256"))?;
+        if let Some(dir) = file.parent() {
+            dirs.insert(dir.to_path_buf());
+        }
+    }
+
+    // Deepest first, so a run directory goes before its date directory.
+    for dir in dirs.into_iter().rev() {
+        let mut dir = dir.as_path();
+        while dir != root && dir.starts_with(&root) && is_spent(dir)? {
+            remove_if_exists(&dir.join("_SUCCESS"))?;
+            match fs::remove_dir(dir) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+            match dir.parent() {
+                Some(up) => dir = up,
+                None => break,
+            }
+        }
+    }
+    if deleted > 0 {
+        info!(template, deleted, days, "retention removed old outputs");
+    }
+    Ok(deleted)
+}
+
+/// Collect data files under `dir`, descending at most `depth` levels.
+/// Checksum sidecars, markers, hidden files (names starting with `_` or `.`)
+/// and the temporary files of writes in flight are skipped.
+fn walk(dir: &Path, depth: Option<usize>, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if name.starts_with(['.', '_']) || name.ends_with(".sha256") || name.contains(TMP_MARKER) {
+            continue;
+        }
+        let kind = entry.file_type()?;
+        if kind.is_dir() {
+            match depth {
+                Some(0) => {}
+                Some(depth) => walk(&entry.path(), Some(depth - 1), files)?,
+                None => walk(&entry.path(), None, files)?,
+            }
+        } else if kind.is_file() {
+            files.push(entry.path());
+        }
+    }
+    Ok(())
+}
+
+/// Empty apart from a success marker.
+fn is_spent(dir: &Path) -> Result<bool> {
+    if !dir.is_dir() {
+        return Ok(false);
+    }
+    for entry in fs::read_dir(dir)? {
+        if entry?.file_name() != "_SUCCESS" {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Remove `path`, returning whether it was still there.
+fn remove_if_exists(path: &Path) -> Result<bool> {
+    match fs::remove_file(path) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// `dir/name` + `suffix`, e.g. `out.csv` -> `out.csv.sha256`.
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+fn parent(path: &Path) -> &Path {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    }
+}
+
+/// Persist the rename itself; a no-op where directories cannot be opened.
+fn sync_dir(path: &Path) -> Result<()> {
+    #[cfg(unix)]
+    File::open(parent(path))?.sync_all()?;
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+/// Write a small file with the same temp-and-rename dance.
+fn write_small(path: &Path, contents: &[u8]) -> Result<()> {
+    let mut file = AtomicFile::create(path)?;
+    io::Write::write_all(file.file(), contents)?;
+    file.commit(FileOptions::default())
+}
+
+fn sha256_file(path: &Path) -> Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh directory under the system temp dir.
+    fn scratch() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("retention-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Create `path` with a modification time `days` days in the past.
+    fn touch(path: &Path, days: u64) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let file = File::create(path).unwrap();
+        file.set_modified(SystemTime::now() - Duration::from_secs(days * 86_400)).unwrap();
+    }
+
+    #[test]
+    fn retention_removes_old_outputs_with_their_sidecars() {
+        let root = scratch();
+        let old = root.join("2020-01-01");
+        touch(&old.join("data.csv"), 30);
+        touch(&old.join("data.csv.sha256"), 30);
+        touch(&old.join("_SUCCESS"), 30);
+        touch(&root.join("2020-02-01/data.csv"), 1);
+
+        let template = format!("{}/{{run_date}}/data.csv", root.display());
+        assert_eq!(apply_retention(&template, 7, false).unwrap(), 1);
+        assert!(!old.exists());
+        assert!(root.join("2020-02-01/data.csv").exists());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn retention_skips_sidecars_markers_and_files_in_flight() {
+        let root = scratch();
+        let part = root.join("2020-01-01/day=1");
+        touch(&part.join("part-0.parquet"), 30);
+        for name in ["part-0.parquet.sha256", "_SUCCESS", ".part-1.parquet.crc", "part-1.parquet.tmp-0"] {
+            touch(&part.join(name), 30);
+        }
+
+        let template = format!("{}/{{run_date}}", root.display());
+        assert_eq!(apply_retention(&template, 7, true).unwrap(), 1);
+        let mut left: Vec<_> = fs::read_dir(&part)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        left.sort();
+        assert_eq!(left, [".part-1.parquet.crc", "_SUCCESS", "part-1.parquet.tmp-0"]);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn retention_treats_missing_files_as_deleted() {
+        let root = scratch();
+        assert!(!remove_if_exists(&root.join("gone.csv")).unwrap());
+        let template = format!("{}/missing/{{run_date}}.csv", root.display());
+        assert_eq!(apply_retention(&template, 7, false).unwrap(), 0);
+        fs::remove_dir_all(root).unwrap();
+    }
+}
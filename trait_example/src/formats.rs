@@ -0,0 +1,391 @@
+use std::{collections::BTreeMap, io::Write};
+
+use polars::prelude::*;
+use serde::Deserialize;
+
+use crate::context;
+use crate::errors::{Error, Result};
+
+// ============================================================================
+// Parquet
+// ============================================================================
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParquetCodec {
+    Uncompressed,
+    Snappy,
+    Gzip,
+    Lz4,
+    #[default]
+    Zstd,
+    Brotli,
+}
+
+/// Writer settings shared by the Parquet and partitioned Parquet sinks.
+///
+/// Metadata values may use the placeholders of
+/// [`RunContext::render`](crate::context::RunContext::render), filled in per run:
+///
+/// ```json
+/// { "compression": "zstd", "compression_level": 9, "row_group_size": 100000,
+///   "metadata": { "job": "{job}", "run_id": "{run_id}", "source": "https://example.com/api" } }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct ParquetSinkOptions {
+    pub compression: ParquetCodec,
+    /// Codec level; gzip 0-9, brotli 0-11, zstd 1-22. `None` uses the codec default.
+    pub compression_level: Option<i32>,
+    /// Rows per row group; `None` uses the Polars default.
+    pub row_group_size: Option<usize>,
+    /// Write min/max/null-count statistics per column chunk.
+    pub statistics: bool,
+    /// Key/value pairs stored in the file footer.
+    pub metadata: BTreeMap<String, String>,
+}
+
+impl Default for ParquetSinkOptions {
+    fn default() -> Self {
+        Self {
+            compression: ParquetCodec::default(),
+            compression_level: None,
+            row_group_size: None,
+            statistics: true,
+            metadata: BTreeMap::new(),
+        }
+    }
+}
+
+impl ParquetSinkOptions {
+    pub fn compression(mut self, codec: ParquetCodec, level: Option<i32>) -> Self {
+        self.compression = codec;
+        self.compression_level = level;
+        self
+    }
+
+    pub fn row_group_size(mut self, rows: usize) -> Self {
+        self.row_group_size = Some(rows.max(1));
+        self
+    }
+
+    pub fn statistics(mut self, enabled: bool) -> Self {
+        self.statistics = enabled;
+        self
+    }
+
+    pub fn metadata(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.metadata.insert(key.into(), value.into());
+        self
+    }
+
+    /// A writer with these settings; metadata is expanded for the current run.
+    pub fn writer<W: Write>(&self, writer: W) -> Result<ParquetWriter<W>> {
+        let level = self.compression_level;
+        let compression = match self.compression {
+            ParquetCodec::Uncompressed => ParquetCompression::Uncompressed,
+            ParquetCodec::Snappy => ParquetCompression::Snappy,
+            ParquetCodec::Lz4 => ParquetCompression::Lz4Raw,
+            ParquetCodec::Gzip => ParquetCompression::Gzip(
+                level.map(|l| GzipLevel::try_new(l.clamp(0, 255) as u8)).transpose()?,
+            ),
+            ParquetCodec::Brotli => ParquetCompression::Brotli(
+                level.map(|l| BrotliLevel::try_new(l.max(0) as u32)).transpose()?,
+            ),
+            ParquetCodec::Zstd => {
+                ParquetCompression::Zstd(level.map(ZstdLevel::try_new).transpose()?)
+            }
+        };
+        let statistics = if self.statistics {
+            StatisticsOptions::default()
+        } else {
+            StatisticsOptions::empty()
+        };
+        let metadata = if self.metadata.is_empty() {
+            None
+        } else {
+            Some(KeyValueMetadata::from_static(
+                self.metadata
+                    .iter()
+                    .map(|(k, v)| Ok((k.clone(), context::expand(v)?)))
+                    .collect::<Result<_>>()?,
+            ))
+        };
+
+        Ok(ParquetWriter::new(writer)
+            .with_compression(compression)
+            .with_statistics(statistics)
+            .with_row_group_size(self.row_group_size)
+            .with_key_value_metadata(metadata))
+    }
+}
+
+// ============================================================================
+// CSV
+// ============================================================================
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CsvQuoteStyle {
+    /// Quote fields containing the delimiter, quotes or newlines.
+    #[default]
+    Necessary,
+    Always,
+    NonNumeric,
+    Never,
+}
+
+/// When the header row is written.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HeaderPolicy {
+    /// Write a header when the file starts empty, so appends do not repeat it.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CsvCompression {
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+}
+
+/// Settings for the CSV sink.
+///
+/// ```json
+/// { "delimiter": ";", "null_value": "NULL", "datetime_format": "%Y-%m-%d %H:%M:%S",
+///   "append": true, "compression": "gzip" }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct CsvSinkOptions {
+    /// A single ASCII character.
+    pub delimiter: char,
+    pub quote_style: CsvQuoteStyle,
+    pub header: HeaderPolicy,
+    pub null_value: String,
+    /// chrono format strings, e.g. `%d.%m.%Y`.
+    pub date_format: Option<String>,
+    pub datetime_format: Option<String>,
+    pub time_format: Option<String>,
+    /// Digits after the decimal point.
+    pub float_precision: Option<usize>,
+    /// `Some(true)` forces scientific notation, `Some(false)` forbids it.
+    pub float_scientific: Option<bool>,
+    /// Append to the target instead of replacing it. Appends are not atomic.
+    pub append: bool,
+    /// Compressed appends add a new gzip member or zstd frame, which
+    /// standard readers decode as one stream.
+    pub compression: CsvCompression,
+    pub compression_level: Option<i32>,
+}
+
+impl Default for CsvSinkOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: ',',
+            quote_style: CsvQuoteStyle::default(),
+            header: HeaderPolicy::default(),
+            null_value: String::new(),
+            date_format: None,
+            datetime_format: None,
+            time_format: None,
+            float_precision: None,
+            float_scientific: None,
+            append: false,
+            compression: CsvCompression::default(),
+            compression_level: None,
+        }
+    }
+}
+
+impl CsvSinkOptions {
+    pub fn delimiter(mut self, delimiter: char) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    pub fn quote_style(mut self, style: CsvQuoteStyle) -> Self {
+        self.quote_style = style;
+        self
+    }
+
+    pub fn header(mut self, policy: HeaderPolicy) -> Self {
+        self.header = policy;
+        self
+    }
+
+    pub fn null_value(mut self, value: impl Into<String>) -> Self {
+        self.null_value = value.into();
+        self
+    }
+
+    pub fn date_format(mut self, format: impl Into<String>) -> Self {
+        self.date_format = Some(format.into());
+        self
+    }
+
+    pub fn datetime_format(mut self, format: impl Into<String>) -> Self {
+        self.datetime_format = Some(format.into());
+        self
+    }
+
+    pub fn float_precision(mut self, digits: usize) -> Self {
+        self.float_precision = Some(digits);
+        self
+    }
+
+    pub fn append(mut self, append: bool) -> Self {
+        self.append = append;
+        self
+    }
+
+    pub fn compression(mut self, compression: CsvCompression, level: Option<i32>) -> Self {
+        self.compression = compression;
+        self.compression_level = level;
+        self
+    }
+
+    /// Whether the first write to a file should include the header.
+    pub fn header_for(&self, new_file: bool) -> bool {
+        match self.header {
+            HeaderPolicy::Auto => new_file,
+            HeaderPolicy::Always => true,
+            HeaderPolicy::Never => false,
+        }
+    }
+
+    /// A CSV writer with these settings over `writer`.
+    pub fn writer<W: Write>(&self, writer: W, header: bool) -> Result<CsvWriter<W>> {
+        if !self.delimiter.is_ascii() {
+            return Err(Error::Config(format!(
+                "CSV delimiter must be ASCII, got {:?}",
+                self.delimiter
+            )));
+        }
+        let quote_style = match self.quote_style {
+            CsvQuoteStyle::Necessary => QuoteStyle::Necessary,
+            CsvQuoteStyle::Always => QuoteStyle::Always,
+            CsvQuoteStyle::NonNumeric => QuoteStyle::NonNumeric,
+            CsvQuoteStyle::Never => QuoteStyle::Never,
+        };
+        Ok(CsvWriter::new(writer)
+            .include_header(header)
+            .with_separator(self.delimiter as u8)
+            .with_quote_style(quote_style)
+            .with_null_value(self.null_value.clone())
+            .with_date_format(self.date_format.clone())
+            .with_datetime_format(self.datetime_format.clone())
+            .with_time_format(self.time_format.clone())
+            .with_float_precision(self.float_precision)
+            .with_float_scientific(self.float_scientific))
+    }
+
+    /// Wrap `writer` in the configured compression.
+    pub fn encoder<W: Write>(&self, writer: W) -> Result<Encoder<W>> {
+        Ok(match self.compression {
+            CsvCompression::None => Encoder::Plain(writer),
+            CsvCompression::Gzip => {
+                let level = self
+                    .compression_level
+                    .map(|l| flate2::Compression::new(l.clamp(0, 9) as u32))
+                    .unwrap_or_default();
+                Encoder::Gzip(flate2::write::GzEncoder::new(writer, level))
+            }
+            CsvCompression::Zstd => Encoder::Zstd(zstd::Encoder::new(
+                writer,
+                self.compression_level.unwrap_or(zstd::DEFAULT_COMPRESSION_LEVEL),
+            )?),
+        })
+    }
+}
+
+/// Optional compression around a file writer. Call [`finish`](Self::finish)
+/// to write the trailer; dropping it leaves a truncated stream.
+pub enum Encoder<W: Write> {
+    Plain(W),
+    Gzip(flate2::write::GzEncoder<W>),
+    Zstd(zstd::Encoder<'static, W>),
+}
+
+impl<W: Write> Encoder<W> {
+    pub fn finish(self) -> Result<W> {
+        Ok(match self {
+            Encoder::Plain(mut w) => {
+                w.flush()?;
+                w
+            }
+            Encoder::Gzip(e) => e.finish()?,
+            Encoder::Zstd(e) => e.finish()?,
+        })
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Encoder::Plain(w) => w.write(buf),
+            Encoder::Gzip(e) => e.write(buf),
+            Encoder::Zstd(e) => e.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Encoder::Plain(w) => w.flush(),
+            Encoder::Gzip(e) => e.flush(),
+            Encoder::Zstd(e) => e.flush(),
+        }
+    }
+}
+
+// ============================================================================
+// JSON
+// ============================================================================
+
+/// Writes batches as the elements of one JSON array, so a stream produces
+/// the same document as a single write of the whole frame.
+pub struct JsonArrayWriter<W: Write> {
+    writer: W,
+    first: bool,
+}
+
+impl<W: Write> JsonArrayWriter<W> {
+    pub fn new(mut writer: W) -> Result<Self> {
+        writer.write_all(b"[")?;
+        Ok(Self {
+            writer,
+            first: true,
+        })
+    }
+
+    pub fn write_batch(&mut self, df: &mut DataFrame) -> Result<()> {
+        if df.height() == 0 {
+            return Ok(());
+        }
+        let mut buf = Vec::new();
+        JsonWriter::new(&mut buf)
+            .with_json_format(JsonFormat::Json)
+            .finish(df)?;
+        // Drop the batch's own brackets and splice its rows into ours.
+        let rows = buf.trim_ascii();
+        let rows = &rows[1..rows.len() - 1];
+        if !self.first {
+            self.writer.write_all(b",")?;
+        }
+        self.writer.write_all(rows)?;
+        self.first = false;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<W> {
+        self.writer.write_all(b"]")?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
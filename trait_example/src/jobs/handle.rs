@@ -0,0 +1,52 @@
+use tokio::task::JoinHandle;
+
+use crate::errors::{Error, Result};
+use crate::metrics::metrics;
+use crate::reports::RunReport;
+
+/// A job running on the Tokio runtime, returned by [`Job::spawn`](super::Job::spawn).
+///
+/// Dropping the handle detaches the job; it keeps running to completion.
+#[derive(Debug)]
+pub struct JobHandle {
+    name: String,
+    task: JoinHandle<Result<RunReport>>,
+}
+
+impl JobHandle {
+    pub(crate) fn new(name: String, task: JoinHandle<Result<RunReport>>) -> Self {
+        Self { name, task }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.task.is_finished()
+    }
+
+    /// Stop the job at its next await point. Open sink transactions are
+    /// dropped and rolled back, and files being replaced are discarded with
+    /// their temporary copy. Only CSV appends write in place and can be left
+    /// partial; a streamed partitioned write keeps the partition files it
+    /// finished but gets no `_SUCCESS` marker.
+    pub fn cancel(&self) {
+        self.task.abort();
+    }
+
+    /// Wait for the job. A cancelled job returns `Error::Cancelled`.
+    pub async fn join(self) -> Result<RunReport> {
+        match self.task.await {
+            Ok(result) => result,
+            Err(e) if e.is_cancelled() => {
+                metrics()
+                    .job_runs
+                    .with_label_values(&[self.name.as_str(), "cancelled"])
+                    .inc();
+                Err(Error::Cancelled(format!("job {} was cancelled", self.name)))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+}
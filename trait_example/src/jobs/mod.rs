@@ -1,56 +1,293 @@
-use tracing::{info, instrument};
+mod handle;
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::path::PathBuf;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use tracing::{error, info, instrument};
 use polars::frame::DataFrame;
+use polars::prelude::LazyFrame;
 use crate::errors::Result;
-use crate::{pipelines::Pipeline, sinks::Sinker, sources::SourceKind};
+use crate::metrics::metrics;
+use crate::pipelines::{Pipeline, SinkPolicy, Stage, StreamOptions};
+use crate::quality::Check;
+use crate::reports::RunReport;
+use crate::schema::SchemaContract;
+use crate::sql::SqlTransform;
 
-type Operation<'a> = Box<dyn Fn(&mut DataFrame) -> Result<DataFrame> + Send + Sync + 'a>;
+pub use handle::JobHandle;
+use crate::{
+    sinks::{SharedSink, Sink},
+    sources::{SharedSource, Source},
+};
 
+/// A job borrows nothing once built from owned values (`String` names,
+/// `'static` closures), so a `Job<'static>` can be cloned, stored and
+/// [spawned](Job::spawn).
+#[derive(Clone)]
 pub struct Job<'a> {
-    name: &'a str,
-    source: SourceKind<'a>,
-    sink: Sinker<'a>,
-    operations: Vec<Operation<'a>>,
+    name: Cow<'a, str>,
+    source: SharedSource<'a>,
+    sinks: Vec<SharedSink<'a>>,
+    sink_policy: SinkPolicy,
+    quarantine: Option<SharedSink<'a>>,
+    stages: Vec<Stage<'a>>,
+    contract: Option<SchemaContract>,
+    history: Option<PathBuf>,
+    streaming: Option<StreamOptions>,
+    dry_run: bool,
+    params: BTreeMap<String, String>,
+    watermarks: BTreeMap<String, String>,
 }
 
 impl<'a> Job<'a> {
-    pub fn new(name: &'a str, source: SourceKind<'a>, sink: Sinker<'a>) -> Self {
+    /// Create a job from any [`Source`] and [`Sink`], built-in or custom.
+    pub fn new(
+        name: impl Into<Cow<'a, str>>,
+        source: impl Source + Send + Sync + 'a,
+        sink: impl Sink + Send + Sync + 'a,
+    ) -> Self {
+        Self::from_shared(name, Arc::new(source), Arc::new(sink))
+    }
+
+    /// Create a job from a source and sink that are already shared, e.g.
+    /// ones built by a [`Registry`](crate::registry::Registry).
+    pub fn from_shared(
+        name: impl Into<Cow<'a, str>>,
+        source: SharedSource<'a>,
+        sink: SharedSink<'a>,
+    ) -> Self {
         Self {
-            name,
+            name: name.into(),
             source,
-            sink,
-            operations: Vec::new(),
+            sinks: vec![sink],
+            sink_policy: SinkPolicy::default(),
+            quarantine: None,
+            stages: Vec::new(),
+            contract: None,
+            history: None,
+            streaming: None,
+            dry_run: false,
+            params: BTreeMap::new(),
+            watermarks: BTreeMap::new(),
         }
     }
     
-    pub fn with_operation<F>(mut self, operation: F) -> Self
+    pub fn with_operation<F>(self, operation: F) -> Self
+    where
+        F: Fn(&mut DataFrame) -> Result<DataFrame> + Send + Sync + 'a,
+    {
+        let name = format!("operation_{}", self.stages.len() + 1);
+        self.with_named_operation(name, operation)
+    }
+
+    /// Add an operation reported under `name` in spans and logs.
+    pub fn with_named_operation<F>(mut self, name: impl Into<Cow<'a, str>>, operation: F) -> Self
     where
         F: Fn(&mut DataFrame) -> Result<DataFrame> + Send + Sync + 'a,
     {
-        self.operations.push(Box::new(operation));
+        self.stages.push(Stage::Operation {
+            name: name.into(),
+            operation: Arc::new(operation),
+        });
+        self
+    }
+
+    /// Add an operation over a `LazyFrame`; consecutive lazy operations are
+    /// collected once as a single plan.
+    pub fn with_lazy_operation<F>(self, operation: F) -> Self
+    where
+        F: Fn(LazyFrame) -> Result<LazyFrame> + Send + Sync + 'a,
+    {
+        let name = format!("operation_{}", self.stages.len() + 1);
+        self.with_named_lazy_operation(name, operation)
+    }
+
+    pub fn with_named_lazy_operation<F>(
+        mut self,
+        name: impl Into<Cow<'a, str>>,
+        operation: F,
+    ) -> Self
+    where
+        F: Fn(LazyFrame) -> Result<LazyFrame> + Send + Sync + 'a,
+    {
+        self.stages.push(Stage::Lazy {
+            name: name.into(),
+            operation: Arc::new(operation),
+        });
+        self
+    }
+
+    /// Add a data quality check between operations.
+    pub fn with_check(mut self, check: Check) -> Self {
+        self.stages.push(Stage::Check(check));
+        self
+    }
+
+    /// Add a SQL statement run against the current frame.
+    pub fn with_sql(mut self, transform: SqlTransform<'a>) -> Self {
+        self.stages.push(Stage::Sql(transform));
+        self
+    }
+
+    /// Also write the output to `sink`; see [`with_sink_policy`](Self::with_sink_policy).
+    pub fn with_sink(self, sink: impl Sink + Send + Sync + 'a) -> Self {
+        self.with_shared_sink(Arc::new(sink))
+    }
+
+    pub fn with_shared_sink(mut self, sink: SharedSink<'a>) -> Self {
+        self.sinks.push(sink);
+        self
+    }
+
+    /// How failures are handled when the job has several sinks.
+    pub fn with_sink_policy(mut self, policy: SinkPolicy) -> Self {
+        self.sink_policy = policy;
+        self
+    }
+
+    /// Send rows rejected by quarantining checks to `sink` instead of dropping them.
+    pub fn with_quarantine(self, sink: impl Sink + Send + Sync + 'a) -> Self {
+        self.with_shared_quarantine(Arc::new(sink))
+    }
+
+    pub fn with_shared_quarantine(mut self, sink: SharedSink<'a>) -> Self {
+        self.quarantine = Some(sink);
+        self
+    }
+
+    /// Check the output against `contract` before it is written.
+    pub fn with_contract(mut self, contract: SchemaContract) -> Self {
+        self.contract = Some(contract);
+        self
+    }
+
+    /// Run in bounded-memory streaming mode; see `PipelineBuilder::streaming`.
+    pub fn with_streaming(mut self, options: StreamOptions) -> Self {
+        self.streaming = Some(options);
+        self
+    }
+
+    /// Plan the writes instead of performing them; see `PipelineBuilder::dry_run`.
+    /// Dry runs are not appended to the run history.
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Set a parameter for path and metadata templates (`{param.<key>}`).
+    pub fn with_param(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.params.insert(key.into(), value.into());
+        self
+    }
+
+    /// Set a watermark for path and metadata templates (`{watermark.<key>}`).
+    pub fn with_watermark(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.watermarks.insert(key.into(), value.into());
+        self
+    }
+
+    /// Append each run's report, failed runs included, as a JSON line to
+    /// `path`.
+    pub fn with_history(mut self, path: impl Into<PathBuf>) -> Self {
+        self.history = Some(path.into());
         self
     }
 
     #[instrument(skip(self), fields(job_name = %self.name))]
-    pub async fn run(&self) -> Result<()> {
-        info!("Running job: {} with {} operations", self.name, self.operations.len());
+    pub async fn run(&self) -> Result<RunReport> {
+        let started = Instant::now();
+        let result = self.run_pipeline().await;
+        let outcome = if result.is_ok() { "success" } else { "failure" };
+
+        let m = metrics();
+        m.job_runs.with_label_values(&[self.name.as_ref(), outcome]).inc();
+        m.job_duration
+            .with_label_values(&[self.name.as_ref(), outcome])
+            .observe(started.elapsed().as_secs_f64());
+        if result.is_ok() {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs_f64())
+                .unwrap_or_default();
+            m.job_last_success.with_label_values(&[self.name.as_ref()]).set(now);
+        }
+        result
+    }
+
+    async fn run_pipeline(&self) -> Result<RunReport> {
+        info!("Running job: {} with {} stages", self.name, self.stages.len());
         
         let mut pipeline_builder = Pipeline::builder()
-            .source(self.source.clone())
-            .sink(self.sink.clone());
+            .name(self.name.as_ref())
+            .shared_source(self.source.clone())
+            .sink_policy(self.sink_policy)
+            .dry_run(self.dry_run);
+        for (key, value) in &self.params {
+            pipeline_builder = pipeline_builder.param(key, value);
+        }
+        for (key, value) in &self.watermarks {
+            pipeline_builder = pipeline_builder.watermark(key, value);
+        }
+        for sink in &self.sinks {
+            pipeline_builder = pipeline_builder.shared_sink(sink.clone());
+        }
+        if let Some(quarantine) = &self.quarantine {
+            pipeline_builder = pipeline_builder.shared_quarantine(quarantine.clone());
+        }
+        if let Some(contract) = &self.contract {
+            pipeline_builder = pipeline_builder.contract(contract.clone());
+        }
+        if let Some(options) = self.streaming {
+            pipeline_builder = pipeline_builder
+                .streaming(options.batch_size)
+                .channel_capacity(options.channel_capacity);
+        }
         
-        // Add all operations
-        for (i, operation) in self.operations.iter().enumerate() {
+        // Add all operations and checks
+        for (i, stage) in self.stages.iter().enumerate() {
             let idx = i;
-            pipeline_builder = pipeline_builder.operation(move |df| {
-                info!("Executing operation {}/{}", idx + 1, self.operations.len());
-                operation(df)
-            });
+            pipeline_builder = match stage {
+                Stage::Operation { name, operation } => {
+                    pipeline_builder.named_operation(name.as_ref(), move |df| {
+                        info!("Executing operation {}/{}", idx + 1, self.stages.len());
+                        operation(df)
+                    })
+                }
+                Stage::Lazy { name, operation } => {
+                    pipeline_builder.named_lazy_operation(name.as_ref(), move |lf| {
+                        info!("Planning lazy operation {}/{}", idx + 1, self.stages.len());
+                        operation(lf)
+                    })
+                }
+                Stage::Check(check) => pipeline_builder.check(check.clone()),
+                Stage::Sql(transform) => pipeline_builder.sql(transform.clone()),
+            };
         }
         
         let pipeline = pipeline_builder.build()?;
-        pipeline.run().await?;
+        let (report, result) = pipeline.run_with_report().await;
+
+        // Failed runs are recorded too, with the check that stopped them. A
+        // history write error is only logged so it can't mask the run's own.
+        if let (Some(path), false) = (&self.history, self.dry_run)
+            && let Err(e) = report.append_to(path)
+        {
+            error!("Job {}: writing run history to {} failed: {}", self.name, path.display(), e);
+        }
+        result?;
         
-        info!("Job {} completed successfully", self.name);
-        Ok(())
+        info!("Job {} completed successfully (run {})", self.name, report.run_id);
+        Ok(report)
     }
-}
\ No newline at end of file
+}
+
+impl Job<'static> {
+    /// Run the job as a Tokio task, e.g. to run several jobs in parallel.
+    pub fn spawn(self) -> JobHandle {
+        let name = self.name.to_string();
+        JobHandle::new(name, tokio::spawn(async move { self.run().await }))
+    }
+}
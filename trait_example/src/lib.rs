@@ -1,6 +1,21 @@
+pub mod config;
+pub mod context;
 pub mod errors;
+pub mod files;
+pub mod formats;
 pub mod jobs;
+pub mod metrics;
+pub mod multi_source;
+pub mod partitioning;
 pub mod pipelines;
+pub mod quality;
+pub mod registry;
+pub mod reports;
+pub mod schema;
 pub mod sinks;
 pub mod sources;
+pub mod sql;
+pub mod sqlite;
+#[cfg(feature = "otel")]
+pub mod telemetry;
 pub mod utils;
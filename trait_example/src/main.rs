@@ -1,16 +1,47 @@
 use std::sync::Arc;
+use std::time::Duration;
 
 use sqlx::postgres::PgPoolOptions;
 use sqlx::{Pool, Postgres};
-use tracing::info;
+use tracing::{error, info};
 use trait_example::errors::Result;
-use trait_example::{jobs::Job, sinks::Sinker, sources::SourceKind as source};
+use trait_example::config::JobConfig;
+use trait_example::reports::RunReport;
+use trait_example::{jobs::Job, metrics, sinks::Sinker, sources::SourceKind as source};
 
+#[cfg(not(feature = "otel"))]
 fn init_tracing() {
     tracing_subscriber::fmt()
         .with_max_level(tracing::Level::INFO)
         .init();
 }
+
+/// `OTEL_EXPORTER_OTLP_ENDPOINT` selects OTLP; otherwise `TRACE_EXPORT=stdout`
+/// or `TRACE_EXPORT=file:<path>` writes spans as JSON lines.
+#[cfg(feature = "otel")]
+fn init_tracing() -> Result<Option<trait_example::telemetry::TelemetryGuard>> {
+    use trait_example::telemetry::{self, TraceExport};
+
+    let export = match (
+        std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT"),
+        std::env::var("TRACE_EXPORT"),
+    ) {
+        (Ok(endpoint), _) => Some(TraceExport::Otlp { endpoint }),
+        (_, Ok(v)) if v == "stdout" => Some(TraceExport::Stdout),
+        (_, Ok(v)) if v.starts_with("file:") => Some(TraceExport::File(v[5..].into())),
+        _ => None,
+    };
+
+    match export {
+        Some(export) => Ok(Some(telemetry::init("trait_example", export)?)),
+        None => {
+            tracing_subscriber::fmt()
+                .with_max_level(tracing::Level::INFO)
+                .init();
+            Ok(None)
+        }
+    }
+}
 async fn setup_postgres_sink<'a>(
     schema: &'a str,
     table: &'a str,
@@ -29,7 +60,24 @@ async fn setup_postgres_sink<'a>(
         primary_key.map(std::borrow::Cow::from),
     ))
 }
+/// `DRY_RUN=1` prints the planned writes instead of performing them.
+fn dry_run() -> bool {
+    matches!(std::env::var("DRY_RUN").as_deref(), Ok("1" | "true"))
+}
+
+fn print_plans(report: &RunReport) -> Result<()> {
+    if report.dry_run {
+        println!("{}", serde_json::to_string_pretty(&report.plans)?);
+    }
+    Ok(())
+}
+
 async fn run() -> Result<()> {
+    // A job file takes precedence over the built-in job below.
+    if let Ok(path) = std::env::var("JOB_CONFIG") {
+        let job = JobConfig::from_path(path)?.into_job()?.with_dry_run(dry_run());
+        return print_plans(&job.spawn().join().await?);
+    }
 
 
     let pool: Arc<Pool<Postgres>> = Arc::new( PgPoolOptions::new()
@@ -191,56 +239,69 @@ async fn run() -> Result<()> {
         }
         
         Ok(result)
-    }).with_operation(|df| {
+    }).with_named_lazy_operation("strip_null_bytes", |mut lf| {
         use polars::prelude::*;
         use tracing::info;
     
         info!("=== Cleaning NULL bytes from text columns ===");
         
-        // Get all string columns
-        let string_columns: Vec<String> = df
-            .get_columns()
+        // One expression per string column, all in a single plan
+        let schema = lf.collect_schema()?;
+        let cleaned: Vec<Expr> = schema
             .iter()
-            .filter_map(|c| {
-                if matches!(c.dtype(), DataType::String) {
-                    Some(c.name().to_string())
-                } else {
-                    None
-                }
+            .filter(|(_, dtype)| matches!(dtype, DataType::String))
+            .map(|(name, _)| {
+                col(name.clone())
+                    .str()
+                    .replace_all(lit("\0"), lit(""), true)  // ✅ Remove NULL bytes
+                    .alias(name.clone())
             })
             .collect();
         
-        info!("Found {} string columns to clean", string_columns.len());
+        info!("Found {} string columns to clean", cleaned.len());
         
-        // Clean each string column
-        let mut cleaned = df.clone();
-        for col_name in &string_columns {
-            info!("  Cleaning column: {}", col_name);
-            
-            cleaned = cleaned
-                .lazy()
-                .with_column(
-                    col(col_name)
-                        .str()
-                        .replace_all(lit("\0"), lit(""), true)  // ✅ Remove NULL bytes
-                        .alias(col_name)
-                )
-                .collect()?;
+        Ok(lf.with_columns(cleaned))
+    });
+
+    print_plans(&job6.with_dry_run(dry_run()).run().await?)
+}
+
+/// Daemon mode: re-run every `RUN_INTERVAL_SECS` and serve `/metrics` on `METRICS_ADDR`.
+async fn run_daemon(interval: Duration) -> Result<()> {
+    let addr = std::env::var("METRICS_ADDR").unwrap_or_else(|_| "0.0.0.0:9898".to_string());
+    tokio::spawn(async move {
+        if let Err(e) = metrics::serve(addr).await {
+            error!("metrics endpoint stopped: {}", e);
         }
-        
-        info!("Cleaned {} columns", string_columns.len());
-        
-        Ok(cleaned)
     });
 
-    job6.run().await?;
-    Ok(())
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        if let Err(e) = run().await {
+            error!("run failed: {}", e);
+        }
+    }
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    #[cfg(feature = "otel")]
+    let _telemetry = init_tracing()?;
+    #[cfg(not(feature = "otel"))]
     init_tracing();
     info!("App starting...");
-    run().await?;
-    Ok(())
+
+    let interval = std::env::var("RUN_INTERVAL_SECS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok());
+    if let Some(secs) = interval {
+        return run_daemon(Duration::from_secs(secs)).await;
+    }
+
+    // One-shot: dump metrics after the run, whatever the outcome. Stderr, so
+    // stdout only carries the dry-run plan.
+    let result = run().await;
+    eprintln!("{}", metrics::render()?);
+    result
 }
@@ -0,0 +1,193 @@
+use std::sync::LazyLock;
+
+use prometheus::{
+    Encoder, GaugeVec, HistogramOpts, HistogramVec, IntCounterVec, Opts, Registry, TextEncoder,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, ToSocketAddrs},
+};
+
+use crate::errors::Result;
+
+// ============================================================================
+// Registry
+// ============================================================================
+
+/// Counters and histograms recorded by pipelines, sources and sinks.
+pub struct Metrics {
+    registry: Registry,
+    pub rows_in: IntCounterVec,
+    pub rows_out: IntCounterVec,
+    pub bytes_fetched: IntCounterVec,
+    pub http_responses: IntCounterVec,
+    pub http_retries: IntCounterVec,
+    pub copy_duration: HistogramVec,
+    pub upsert_rows: IntCounterVec,
+    pub sink_duration: HistogramVec,
+    pub pipeline_duration: HistogramVec,
+    pub job_runs: IntCounterVec,
+    pub job_duration: HistogramVec,
+    pub job_last_success: GaugeVec,
+}
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+/// Process-wide metrics registry.
+pub fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new_custom(Some("etl".to_string()), None)
+            .expect("valid metrics prefix");
+
+        let rows_in = IntCounterVec::new(
+            Opts::new("pipeline_rows_in_total", "Rows loaded from the source"),
+            &["pipeline", "source"],
+        )
+        .expect("valid metric");
+        let rows_out = IntCounterVec::new(
+            Opts::new("pipeline_rows_out_total", "Rows handed to the sink"),
+            &["pipeline", "sink"],
+        )
+        .expect("valid metric");
+        let bytes_fetched = IntCounterVec::new(
+            Opts::new("source_bytes_fetched_total", "Response bytes read by sources"),
+            &["source"],
+        )
+        .expect("valid metric");
+        let http_responses = IntCounterVec::new(
+            Opts::new("source_http_responses_total", "HTTP responses by status code"),
+            &["status"],
+        )
+        .expect("valid metric");
+        let http_retries = IntCounterVec::new(
+            Opts::new("source_http_retries_total", "HTTP requests retried"),
+            &["reason"],
+        )
+        .expect("valid metric");
+        let copy_duration = HistogramVec::new(
+            HistogramOpts::new("sink_copy_duration_seconds", "Duration of Postgres COPY"),
+            &["mode"],
+        )
+        .expect("valid metric");
+        let upsert_rows = IntCounterVec::new(
+            Opts::new("sink_upsert_rows_total", "Upserted rows by outcome"),
+            &["table", "outcome"],
+        )
+        .expect("valid metric");
+        let sink_duration = HistogramVec::new(
+            HistogramOpts::new("sink_save_duration_seconds", "Duration of Sink::save_data"),
+            &["sink", "outcome"],
+        )
+        .expect("valid metric");
+        let pipeline_duration = HistogramVec::new(
+            HistogramOpts::new("pipeline_duration_seconds", "Duration of Pipeline::run")
+                .buckets(prometheus::exponential_buckets(0.5, 2.0, 12).expect("valid buckets")),
+            &["pipeline", "outcome"],
+        )
+        .expect("valid metric");
+        let job_runs = IntCounterVec::new(
+            Opts::new("job_runs_total", "Job runs by outcome"),
+            &["job", "outcome"],
+        )
+        .expect("valid metric");
+        let job_duration = HistogramVec::new(
+            HistogramOpts::new("job_duration_seconds", "Duration of Job::run")
+                .buckets(prometheus::exponential_buckets(0.5, 2.0, 12).expect("valid buckets")),
+            &["job", "outcome"],
+        )
+        .expect("valid metric");
+        let job_last_success = GaugeVec::new(
+            Opts::new(
+                "job_last_success_timestamp_seconds",
+                "Unix time of the last successful run",
+            ),
+            &["job"],
+        )
+        .expect("valid metric");
+
+        for collector in [
+            Box::new(rows_in.clone()) as Box<dyn prometheus::core::Collector>,
+            Box::new(rows_out.clone()),
+            Box::new(bytes_fetched.clone()),
+            Box::new(http_responses.clone()),
+            Box::new(http_retries.clone()),
+            Box::new(copy_duration.clone()),
+            Box::new(upsert_rows.clone()),
+            Box::new(sink_duration.clone()),
+            Box::new(pipeline_duration.clone()),
+            Box::new(job_runs.clone()),
+            Box::new(job_duration.clone()),
+            Box::new(job_last_success.clone()),
+        ] {
+            registry.register(collector).expect("unique metric names");
+        }
+
+        Self {
+            registry,
+            rows_in,
+            rows_out,
+            bytes_fetched,
+            http_responses,
+            http_retries,
+            copy_duration,
+            upsert_rows,
+            sink_duration,
+            pipeline_duration,
+            job_runs,
+            job_duration,
+            job_last_success,
+        }
+    }
+}
+
+/// Render all metrics in the Prometheus text exposition format.
+pub fn render() -> Result<String> {
+    let mut buf = Vec::new();
+    TextEncoder::new().encode(&metrics().registry.gather(), &mut buf)?;
+    Ok(String::from_utf8_lossy(&buf).into_owned())
+}
+
+// ============================================================================
+// HTTP endpoint
+// ============================================================================
+
+/// Serve `GET /metrics` until the task is dropped.
+///
+/// Deliberately tiny: one request per connection, no keep-alive.
+pub async fn serve(addr: impl ToSocketAddrs) -> Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    tracing::info!("metrics endpoint listening on {}", listener.local_addr()?);
+
+    loop {
+        let (mut stream, _) = listener.accept().await?;
+        tokio::spawn(async move {
+            let mut buf = [0u8; 1024];
+            let n = match stream.read(&mut buf).await {
+                Ok(n) => n,
+                Err(e) => {
+                    tracing::warn!("metrics request read failed: {}", e);
+                    return;
+                }
+            };
+            let request = String::from_utf8_lossy(&buf[..n]);
+            let path = request.split_whitespace().nth(1).unwrap_or("");
+
+            let (status, body) = match (path, render()) {
+                ("/metrics", Ok(body)) => ("200 OK", body),
+                ("/metrics", Err(e)) => ("500 Internal Server Error", e.to_string()),
+                _ => ("404 Not Found", "not found\n".to_string()),
+            };
+            let response = format!(
+                "HTTP/1.1 {status}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len(),
+            );
+            if let Err(e) = stream.write_all(response.as_bytes()).await {
+                tracing::warn!("metrics response write failed: {}", e);
+            }
+        });
+    }
+}
@@ -0,0 +1,157 @@
+use std::{borrow::Cow, sync::Arc};
+
+use async_trait::async_trait;
+use futures_util::future::try_join_all;
+use polars::prelude::*;
+use serde::Deserialize;
+
+use crate::errors::{Error, Result};
+use crate::sources::{SharedSource, Source};
+use crate::utils::collect_lazy;
+
+/// Join type for [`MultiSource::join`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JoinKind {
+    #[default]
+    Inner,
+    Left,
+    /// Full outer join; key columns are coalesced.
+    Outer,
+}
+
+#[derive(Clone, Debug)]
+enum Combine {
+    Join { on: Vec<String>, how: JoinKind },
+    Union { source_column: Option<String> },
+}
+
+/// Several named sources loaded concurrently and combined into one frame.
+///
+/// Joins run left to right in insertion order, so with a left join the first
+/// source decides which rows are kept. Non-key columns that clash with a
+/// column already in the frame get the source's name as a suffix (`title_users`).
+///
+/// ```ignore
+/// let source = MultiSource::join(["id"], JoinKind::Left)
+///     .source("pages", SourceKind::read_http("https://a.example/pages"))
+///     .source("users", SourceKind::read_http("https://b.example/users"));
+/// let pipeline = Pipeline::builder().source(source).sink(sink).build()?;
+/// ```
+#[derive(Clone)]
+pub struct MultiSource<'a> {
+    sources: Vec<(Cow<'a, str>, SharedSource<'a>)>,
+    combine: Combine,
+}
+
+impl<'a> MultiSource<'a> {
+    /// Join the sources on `on`, which every source must contain.
+    pub fn join<S: Into<String>>(on: impl IntoIterator<Item = S>, how: JoinKind) -> Self {
+        Self {
+            sources: Vec::new(),
+            combine: Combine::Join {
+                on: on.into_iter().map(Into::into).collect(),
+                how,
+            },
+        }
+    }
+
+    /// Stack the sources by column name. Columns missing from a source are
+    /// filled with nulls and differing dtypes are widened to a common type.
+    pub fn union() -> Self {
+        Self {
+            sources: Vec::new(),
+            combine: Combine::Union {
+                source_column: None,
+            },
+        }
+    }
+
+    /// For unions, record each row's source name in `column`.
+    pub fn with_source_column(mut self, column: impl Into<String>) -> Self {
+        if let Combine::Union { source_column } = &mut self.combine {
+            *source_column = Some(column.into());
+        }
+        self
+    }
+
+    pub fn source(self, name: impl Into<Cow<'a, str>>, source: impl Source + Send + Sync + 'a) -> Self {
+        self.shared_source(name, Arc::new(source))
+    }
+
+    pub fn shared_source(mut self, name: impl Into<Cow<'a, str>>, source: SharedSource<'a>) -> Self {
+        self.sources.push((name.into(), source));
+        self
+    }
+
+    fn combine(&self, frames: Vec<LazyFrame>) -> Result<LazyFrame> {
+        let names = self.sources.iter().map(|(name, _)| name.as_ref());
+        match &self.combine {
+            Combine::Join { on, how } => {
+                let keys: Vec<Expr> = on.iter().map(|c| col(c.as_str())).collect();
+                let mut frames = names.zip(frames);
+                let (_, mut joined) = frames
+                    .next()
+                    .ok_or_else(|| Error::Config("join needs at least one source".to_string()))?;
+                for (name, frame) in frames {
+                    let args = match how {
+                        JoinKind::Inner => JoinArgs::new(JoinType::Inner),
+                        JoinKind::Left => JoinArgs::new(JoinType::Left),
+                        JoinKind::Outer => JoinArgs::new(JoinType::Full)
+                            .with_coalesce(JoinCoalesce::CoalesceColumns),
+                    }
+                    .with_suffix(Some(format!("_{name}").into()));
+                    joined = joined.join(frame, &keys, &keys, args);
+                }
+                Ok(joined)
+            }
+            Combine::Union { source_column } => {
+                let frames: Vec<LazyFrame> = names
+                    .zip(frames)
+                    .map(|(name, frame)| match source_column {
+                        Some(column) => frame.with_column(lit(name).alias(column.as_str())),
+                        None => frame,
+                    })
+                    .collect();
+                if frames.is_empty() {
+                    return Err(Error::Config("union needs at least one source".to_string()));
+                }
+                let args = UnionArgs {
+                    to_supertypes: true,
+                    ..Default::default()
+                };
+                Ok(concat_lf_diagonal(frames, args)?)
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Source for MultiSource<'_> {
+    fn kind(&self) -> &'static str {
+        match self.combine {
+            Combine::Join { .. } => "join",
+            Combine::Union { .. } => "union",
+        }
+    }
+
+    async fn load_data(&self) -> Result<DataFrame> {
+        collect_lazy(self.scan().await?).await
+    }
+
+    /// Scan every source concurrently and combine the plans, so file sources
+    /// still get filters and projections pushed down.
+    async fn scan(&self) -> Result<LazyFrame> {
+        let frames = try_join_all(self.sources.iter().map(|(_, source)| source.scan())).await?;
+        self.combine(frames)
+    }
+}
+
+impl std::fmt::Debug for MultiSource<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MultiSource")
+            .field("sources", &self.sources.iter().map(|(n, s)| (n, s.kind())).collect::<Vec<_>>())
+            .field("combine", &self.combine)
+            .finish()
+    }
+}
@@ -0,0 +1,215 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use polars::prelude::*;
+use serde::Deserialize;
+
+use crate::errors::{Error, Result};
+use crate::files::{AtomicFile, FileOptions};
+use crate::formats::ParquetSinkOptions;
+
+/// Directory name Hive and Spark use for null and empty partition values.
+pub const HIVE_DEFAULT_PARTITION: &str = "__HIVE_DEFAULT_PARTITION__";
+
+/// What happens to files already in a partition directory.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PartitionMode {
+    /// Add new files next to existing ones.
+    #[default]
+    Append,
+    /// Replace the files of every partition present in the frame; other
+    /// partitions are left alone.
+    OverwritePartition,
+}
+
+/// Escape a partition value like Hive's `FileUtils.escapePathName`.
+pub fn hive_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        let needs_escape = matches!(
+            c,
+            '\u{00}'..='\u{1F}'
+                | '"' | '#' | '%' | '\'' | '*' | '/' | ':' | '=' | '?' | '\\'
+                | '\u{7F}' | '{' | '[' | ']' | '^'
+        );
+        if needs_escape {
+            escaped.push_str(&format!("%{:02X}", c as u32));
+        } else {
+            escaped.push(c);
+        }
+    }
+    escaped
+}
+
+/// One output file of a partitioned write.
+pub struct PartFile {
+    pub path: PathBuf,
+    pub df: DataFrame,
+}
+
+/// Split `df` into Hive partition files under `root`; partition columns are
+/// encoded in the directory names and dropped from the files.
+pub fn plan_partitions(
+    df: &DataFrame,
+    root: &Path,
+    partition_by: &[String],
+    max_rows_per_file: Option<usize>,
+) -> Result<Vec<PartFile>> {
+    if partition_by.is_empty() {
+        return Err(Error::Config("partitioned sink needs partition columns".to_string()));
+    }
+    // Unique per write, so appends never clash with earlier files.
+    let prefix = &uuid::Uuid::new_v4().simple().to_string()[..8];
+
+    let mut files = Vec::new();
+    // Null and "" share the default directory, so number files per directory.
+    let mut counts: HashMap<PathBuf, usize> = HashMap::new();
+    for part in df.partition_by_stable(partition_by.iter().map(String::as_str), true)? {
+        let mut dir = root.to_path_buf();
+        for column in partition_by {
+            let value = part.column(column)?.get(0)?;
+            let value = match value {
+                AnyValue::Null => HIVE_DEFAULT_PARTITION.to_string(),
+                AnyValue::String("") => HIVE_DEFAULT_PARTITION.to_string(),
+                AnyValue::StringOwned(s) if s.is_empty() => HIVE_DEFAULT_PARTITION.to_string(),
+                AnyValue::String(s) => hive_escape(s),
+                AnyValue::StringOwned(s) => hive_escape(&s),
+                other => hive_escape(&other.to_string()),
+            };
+            dir.push(format!("{}={}", hive_escape(column), value));
+        }
+
+        let data = part.drop_many(partition_by);
+        let rows = max_rows_per_file.unwrap_or(data.height()).max(1);
+        let count = counts.entry(dir.clone()).or_default();
+        for start in (0..data.height()).step_by(rows) {
+            let n = *count;
+            *count += 1;
+            files.push(PartFile {
+                path: dir.join(format!("part-{prefix}-{n:05}.parquet")),
+                df: data.slice(start as i64, rows),
+            });
+        }
+    }
+    Ok(files)
+}
+
+/// Write the partition files. With `OverwritePartition`, files found in a
+/// partition directory before this write are removed once the new files are
+/// in place; `cleaned` remembers directories already replaced in this run so
+/// streamed batches do not delete each other.
+pub fn write_partitions(
+    files: Vec<PartFile>,
+    mode: PartitionMode,
+    parquet: &ParquetSinkOptions,
+    options: FileOptions,
+    cleaned: &mut HashSet<PathBuf>,
+) -> Result<()> {
+    let mut stale = Vec::new();
+    for file in &files {
+        let Some(dir) = file.path.parent() else {
+            continue;
+        };
+        if mode == PartitionMode::OverwritePartition && cleaned.insert(dir.to_path_buf()) {
+            stale.extend(list_files(dir)?);
+        }
+        fs::create_dir_all(dir)?;
+    }
+
+    let per_file = FileOptions {
+        success_marker: false,
+        ..options
+    };
+    for PartFile { path, mut df } in files {
+        let mut out = AtomicFile::create(&path)?;
+        parquet.writer(out.file())?.finish(&mut df)?;
+        out.commit(per_file)?;
+    }
+
+    for path in stale {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+/// Data files (and their sidecars) in `dir`; hidden and `_` files are skipped.
+fn list_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut files = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if entry.file_type()?.is_file() && !name.starts_with('.') && !name.starts_with('_') {
+            files.push(entry.path());
+        }
+    }
+    Ok(files)
+}
+
+/// Touch `_SUCCESS` in the dataset root.
+pub fn write_success_marker(root: &Path) -> Result<()> {
+    fs::create_dir_all(root)?;
+    let out = AtomicFile::create(root.join("_SUCCESS"))?;
+    out.commit(FileOptions::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dirs(files: &[PartFile], root: &Path) -> Vec<String> {
+        files
+            .iter()
+            .map(|f| {
+                let dir = f.path.parent().unwrap().strip_prefix(root).unwrap();
+                dir.to_string_lossy().into_owned()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn hive_escape_encodes_control_and_reserved_characters() {
+        assert_eq!(hive_escape("plain value-1.0"), "plain value-1.0");
+        assert_eq!(hive_escape("a/b=c"), "a%2Fb%3Dc");
+        assert_eq!(hive_escape("50%"), "50%25");
+        assert_eq!(hive_escape("\0\n\u{7F}"), "%00%0A%7F");
+        assert_eq!(hive_escape("ü"), "ü");
+    }
+
+    #[test]
+    fn nulls_and_empty_strings_share_the_default_partition() {
+        let df = df!(
+            "region" => [Some("eu/west"), None, Some(""), Some("us")],
+            "n" => [1i64, 2, 3, 4],
+        )
+        .unwrap();
+        let root = Path::new("out");
+        let files = plan_partitions(&df, root, &["region".to_string()], None).unwrap();
+
+        let default = format!("region={HIVE_DEFAULT_PARTITION}");
+        assert_eq!(dirs(&files, root), ["region=eu%2Fwest", &default, &default, "region=us"]);
+        // Files in the shared directory are numbered, not overwritten.
+        assert_ne!(files[1].path, files[2].path);
+        assert!(files.iter().all(|f| f.df.get_column_names() == ["n"]));
+    }
+
+    #[test]
+    fn plan_partitions_splits_large_partitions() {
+        let df = df!("day" => [1i64, 1, 1, 2], "n" => [1i64, 2, 3, 4]).unwrap();
+        let root = Path::new("out");
+        let files = plan_partitions(&df, root, &["day".to_string()], Some(2)).unwrap();
+
+        assert_eq!(dirs(&files, root), ["day=1", "day=1", "day=2"]);
+        assert_eq!(files.iter().map(|f| f.df.height()).collect::<Vec<_>>(), [2, 1, 1]);
+        assert!(plan_partitions(&df, root, &[], None).is_err());
+    }
+}
@@ -0,0 +1,292 @@
+use std::time::Instant;
+
+use futures_util::future::join_all;
+use polars::frame::DataFrame;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::{self, Receiver, Sender};
+use tracing::{info_span, Instrument};
+
+use crate::errors::{Error, Result};
+use crate::reports::SinkReport;
+use crate::sinks::{SharedSink, UpsertCounts};
+
+/// How a pipeline with several sinks handles sink failures.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SinkPolicy {
+    /// Write to every sink concurrently; the run fails if any sink fails.
+    /// Sinks that succeeded are not rolled back.
+    #[default]
+    All,
+    /// Write concurrently and report failures; the run fails only if every
+    /// sink fails.
+    BestEffort,
+    /// Write one sink at a time in insertion order and stop at the first
+    /// failure. Streaming runs write concurrently and behave like `All`.
+    Ordered,
+}
+
+impl SinkPolicy {
+    fn stops_on_failure(self) -> bool {
+        self != SinkPolicy::BestEffort
+    }
+}
+
+/// Outcome of one sink, kept with its error until the policy is applied.
+type Outcome = (SinkReport, Result<()>);
+
+async fn timed<F>(index: usize, kind: &'static str, write: F) -> Outcome
+where
+    F: Future<Output = Result<(usize, Option<UpsertCounts>)>>,
+{
+    let started = Instant::now();
+    let result = write.await;
+    let (rows, upserts) = *result.as_ref().unwrap_or(&(0, None));
+    let report = SinkReport {
+        index,
+        sink: kind.to_string(),
+        rows,
+        duration_ms: started.elapsed().as_millis() as u64,
+        error: result.as_ref().err().map(ToString::to_string),
+        upserts,
+    };
+    (report, result.map(|_| ()))
+}
+
+/// Write `df` to every sink according to `policy`.
+pub(crate) async fn save_all<'a>(
+    sinks: &[SharedSink<'a>],
+    policy: SinkPolicy,
+    df: &DataFrame,
+) -> Result<Vec<SinkReport>> {
+    let save = |index: usize, sink: &SharedSink<'a>| {
+        let mut df = df.clone();
+        let span = info_span!("sink.save", sink = sink.kind(), index, rows = df.height());
+        let sink = sink.clone();
+        timed(index, sink.kind(), async move {
+            let upserts = sink.save_data_with_counts(&mut df).await?;
+            Ok((df.height(), upserts))
+        })
+        .instrument(span)
+    };
+
+    let outcomes = match policy {
+        SinkPolicy::Ordered => {
+            let mut outcomes = Vec::with_capacity(sinks.len());
+            for (index, sink) in sinks.iter().enumerate() {
+                let outcome = save(index, sink).await;
+                let failed = outcome.1.is_err();
+                outcomes.push(outcome);
+                if failed {
+                    break;
+                }
+            }
+            outcomes
+        }
+        SinkPolicy::All | SinkPolicy::BestEffort => {
+            join_all(sinks.iter().enumerate().map(|(index, sink)| save(index, sink))).await
+        }
+    };
+    resolve(policy, outcomes)
+}
+
+/// Streaming counterpart of [`save_all`]: every batch from `rx` is sent to
+/// each sink's `save_stream`.
+pub(crate) async fn save_stream_all(
+    sinks: &[SharedSink<'_>],
+    policy: SinkPolicy,
+    capacity: usize,
+    rx: Receiver<DataFrame>,
+) -> Result<Vec<SinkReport>> {
+    let (txs, consumers): (Vec<_>, Vec<_>) = sinks
+        .iter()
+        .enumerate()
+        .map(|(index, sink)| {
+            let (tx, rx) = mpsc::channel(capacity);
+            let span = info_span!("sink.stream", sink = sink.kind(), index);
+            let consume = timed(index, sink.kind(), sink.save_stream_with_counts(rx)).instrument(span);
+            (tx, consume)
+        })
+        .unzip();
+
+    let ((), outcomes) = tokio::join!(fan_out(rx, txs, policy), join_all(consumers));
+    resolve(policy, outcomes)
+}
+
+/// Copy each batch to every open sink channel. Under a stopping policy the
+/// first closed channel (a failed sink) ends the run for all sinks.
+async fn fan_out(mut rx: Receiver<DataFrame>, txs: Vec<Sender<DataFrame>>, policy: SinkPolicy) {
+    while let Some(df) = rx.recv().await {
+        let mut open = 0;
+        for tx in &txs {
+            if tx.send(df.clone()).await.is_ok() {
+                open += 1;
+            } else if policy.stops_on_failure() {
+                return;
+            }
+        }
+        if open == 0 {
+            return;
+        }
+    }
+}
+
+/// Apply the policy: log every failure and return the first one if the run
+/// should fail.
+fn resolve(policy: SinkPolicy, outcomes: Vec<Outcome>) -> Result<Vec<SinkReport>> {
+    let mut reports = Vec::with_capacity(outcomes.len());
+    let mut first_error: Option<Error> = None;
+    let mut failures = 0;
+
+    for (report, result) in outcomes {
+        if let Err(e) = result {
+            tracing::error!("sink {} ({}) failed: {}", report.index, report.sink, e);
+            failures += 1;
+            first_error.get_or_insert(e);
+        }
+        reports.push(report);
+    }
+
+    let fatal = match policy {
+        SinkPolicy::BestEffort => failures == reports.len(),
+        SinkPolicy::All | SinkPolicy::Ordered => failures > 0,
+    };
+    match first_error {
+        Some(e) if fatal => Err(e),
+        _ => Ok(reports),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    };
+
+    use async_trait::async_trait;
+    use polars::prelude::*;
+
+    use super::*;
+    use crate::sinks::Sink;
+
+    /// Counts its writes; fails every one if `fail` is set.
+    #[derive(Default)]
+    struct Probe {
+        writes: AtomicUsize,
+        fail: bool,
+    }
+
+    #[async_trait]
+    impl Sink for Probe {
+        async fn save_data(&self, _df: &mut DataFrame) -> Result<()> {
+            self.writes.fetch_add(1, Ordering::SeqCst);
+            if self.fail {
+                return Err(Error::Config("probe failed".into()));
+            }
+            Ok(())
+        }
+    }
+
+    fn probes(fails: &[bool]) -> (Vec<Arc<Probe>>, Vec<SharedSink<'static>>) {
+        let probes: Vec<_> = fails
+            .iter()
+            .map(|&fail| {
+                Arc::new(Probe {
+                    fail,
+                    ..Probe::default()
+                })
+            })
+            .collect();
+        let sinks = probes.iter().map(|p| p.clone() as SharedSink).collect();
+        (probes, sinks)
+    }
+
+    fn writes(probes: &[Arc<Probe>]) -> Vec<usize> {
+        probes
+            .iter()
+            .map(|p| p.writes.load(Ordering::SeqCst))
+            .collect()
+    }
+
+    fn frame() -> DataFrame {
+        df!("id" => [1i64, 2, 3]).unwrap()
+    }
+
+    #[tokio::test]
+    async fn all_writes_every_sink_and_fails_on_any_failure() {
+        let (probes, sinks) = probes(&[false, true, false]);
+        assert!(save_all(&sinks, SinkPolicy::All, &frame()).await.is_err());
+        assert_eq!(writes(&probes), vec![1, 1, 1]);
+    }
+
+    #[tokio::test]
+    async fn best_effort_fails_only_when_every_sink_fails() {
+        let (_, sinks) = probes(&[false, true]);
+        let reports = save_all(&sinks, SinkPolicy::BestEffort, &frame())
+            .await
+            .unwrap();
+        assert_eq!(reports[0].rows, 3);
+        assert!(reports[0].error.is_none());
+        assert_eq!(reports[1].rows, 0);
+        assert!(
+            reports[1]
+                .error
+                .as_deref()
+                .unwrap()
+                .contains("probe failed")
+        );
+
+        let (_, sinks) = probes(&[true, true]);
+        assert!(
+            save_all(&sinks, SinkPolicy::BestEffort, &frame())
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn ordered_stops_at_the_first_failure() {
+        let (probes, sinks) = probes(&[false, true, false]);
+        assert!(
+            save_all(&sinks, SinkPolicy::Ordered, &frame())
+                .await
+                .is_err()
+        );
+        assert_eq!(writes(&probes), vec![1, 1, 0]);
+    }
+
+    #[tokio::test]
+    async fn streaming_sends_every_batch_to_every_sink() {
+        let (probes, sinks) = probes(&[false, false]);
+        let (tx, rx) = mpsc::channel(1);
+        let send = async move {
+            for _ in 0..5 {
+                tx.send(frame()).await.unwrap();
+            }
+        };
+        let (reports, ()) = tokio::join!(save_stream_all(&sinks, SinkPolicy::All, 1, rx), send);
+        let reports = reports.unwrap();
+        assert_eq!(writes(&probes), vec![5, 5]);
+        assert!(reports.iter().all(|r| r.rows == 15));
+    }
+
+    #[tokio::test]
+    async fn streaming_best_effort_keeps_feeding_healthy_sinks() {
+        let (probes, sinks) = probes(&[true, false]);
+        let (tx, rx) = mpsc::channel(1);
+        let send = async move {
+            for _ in 0..5 {
+                if tx.send(frame()).await.is_err() {
+                    break;
+                }
+            }
+        };
+        let (reports, ()) =
+            tokio::join!(save_stream_all(&sinks, SinkPolicy::BestEffort, 1, rx), send);
+        let reports = reports.unwrap();
+        assert_eq!(writes(&probes), vec![1, 5]);
+        assert!(reports[0].error.is_some());
+        assert_eq!(reports[1].rows, 15);
+    }
+}
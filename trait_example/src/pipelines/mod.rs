@@ -1,56 +1,311 @@
-use polars::frame::DataFrame;
+mod fanout;
+
+use std::{borrow::Cow, collections::BTreeMap, sync::Arc};
+
+use futures_util::future::try_join_all;
+use polars::{
+    frame::DataFrame,
+    functions::concat_df_diagonal,
+    prelude::{IntoLazy, LazyFrame},
+};
+use tokio::sync::mpsc;
+use tracing::{field, info_span, Instrument};
+
+use crate::context::RunContext;
 use crate::errors::{Error, Result};
+use crate::metrics::metrics;
+use crate::quality::{annotate_rejected, Check, CheckReport};
+use crate::reports::{now_ms, RunReport};
+use crate::schema::SchemaContract;
+use crate::sql::SqlTransform;
+use crate::utils::collect_lazy;
+
+pub use fanout::SinkPolicy;
 use crate::{
-    sinks::{Sink, Sinker},
-    sources::{Source, SourceKind},
+    sinks::{SharedSink, Sink, SinkPlan},
+    sources::{SharedSource, Source},
 };
 
+/// Operations are reference counted so jobs can be cloned and moved into tasks.
+pub type Operation<'a> = Arc<dyn Fn(&mut DataFrame) -> Result<DataFrame> + Send + Sync + 'a>;
+
+/// An operation that extends the query plan instead of materializing a frame.
+pub type LazyOperation<'a> = Arc<dyn Fn(LazyFrame) -> Result<LazyFrame> + Send + Sync + 'a>;
+
+/// One step between source and sink, run in insertion order.
+#[derive(Clone)]
+pub(crate) enum Stage<'a> {
+    /// An operation plus the name its span and logs are reported under.
+    Operation {
+        name: Cow<'a, str>,
+        operation: Operation<'a>,
+    },
+    /// Consecutive lazy operations are composed into one plan.
+    Lazy {
+        name: Cow<'a, str>,
+        operation: LazyOperation<'a>,
+    },
+    Check(Check),
+    Sql(SqlTransform<'a>),
+}
+
+/// Batch size and channel depth for streaming runs.
+#[derive(Clone, Copy, Debug)]
+pub struct StreamOptions {
+    pub batch_size: usize,
+    pub channel_capacity: usize,
+}
+
+impl Default for StreamOptions {
+    fn default() -> Self {
+        Self {
+            batch_size: 100_000,
+            channel_capacity: 4,
+        }
+    }
+}
+
 pub struct PipelineBuilder<'a> {
-    source: Option<SourceKind<'a>>,
-    sink: Option<Sinker<'a>>,
-    operations: Vec<Box<dyn Fn(&mut DataFrame) -> Result<DataFrame> + Send + Sync + 'a>>,
+    name: Cow<'a, str>,
+    source: Option<SharedSource<'a>>,
+    sinks: Vec<SharedSink<'a>>,
+    sink_policy: SinkPolicy,
+    quarantine: Option<SharedSink<'a>>,
+    stages: Vec<Stage<'a>>,
+    contract: Option<SchemaContract>,
+    streaming: Option<StreamOptions>,
+    dry_run: bool,
+    params: BTreeMap<String, String>,
+    watermarks: BTreeMap<String, String>,
+}
+
+impl Default for PipelineBuilder<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<'a> PipelineBuilder<'a> {
     pub fn new() -> Self {
         Self {
+            name: Cow::Borrowed("pipeline"),
             source: None,
-            sink: None,
-            operations: Vec::new(),
+            sinks: Vec::new(),
+            sink_policy: SinkPolicy::default(),
+            quarantine: None,
+            stages: Vec::new(),
+            contract: None,
+            streaming: None,
+            dry_run: false,
+            params: BTreeMap::new(),
+            watermarks: BTreeMap::new(),
         }
     }
     
-    pub fn source(mut self, source: SourceKind<'a>) -> Self {
+    /// Name used to label metrics; defaults to `"pipeline"`.
+    pub fn name(mut self, name: impl Into<Cow<'a, str>>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// Any [`Source`]: a built-in `SourceKind` or your own implementation.
+    pub fn source(self, source: impl Source + Send + Sync + 'a) -> Self {
+        self.shared_source(Arc::new(source))
+    }
+
+    /// Like [`source`](Self::source), for a source that is already shared.
+    pub fn shared_source(mut self, source: SharedSource<'a>) -> Self {
         self.source = Some(source);
         self
     }
     
-    pub fn sink(mut self, sink: Sinker<'a>) -> Self {
-        self.sink = Some(sink);
+    /// Add a sink: a built-in `Sinker` or your own implementation. Calling
+    /// this again fans the output out to several sinks; see
+    /// [`sink_policy`](Self::sink_policy).
+    pub fn sink(self, sink: impl Sink + Send + Sync + 'a) -> Self {
+        self.shared_sink(Arc::new(sink))
+    }
+
+    pub fn shared_sink(mut self, sink: SharedSink<'a>) -> Self {
+        self.sinks.push(sink);
+        self
+    }
+
+    /// How failures are handled when there are several sinks; defaults to
+    /// [`SinkPolicy::All`].
+    pub fn sink_policy(mut self, policy: SinkPolicy) -> Self {
+        self.sink_policy = policy;
         self
     }
     
-    pub fn operation<F>(mut self, op: F) -> Self
+    /// Where rows rejected by quarantining checks are written, tagged with
+    /// `_rule`, `_stage` and `_run_id`. Without one they are dropped.
+    pub fn quarantine(self, sink: impl Sink + Send + Sync + 'a) -> Self {
+        self.shared_quarantine(Arc::new(sink))
+    }
+
+    pub fn shared_quarantine(mut self, sink: SharedSink<'a>) -> Self {
+        self.quarantine = Some(sink);
+        self
+    }
+
+    /// Enforce `contract` on the output of the last stage, before any sink
+    /// sees it. In streaming mode every batch is checked.
+    pub fn contract(mut self, contract: SchemaContract) -> Self {
+        self.contract = Some(contract);
+        self
+    }
+
+    /// Read the source and run every stage, but ask the sinks for a
+    /// [`SinkPlan`] instead of writing. Plans land in `RunReport::plans`.
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// A job parameter, available to path and metadata templates as
+    /// `{param.<key>}`.
+    pub fn param(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.params.insert(key.into(), value.into());
+        self
+    }
+
+    /// The high-water mark an incremental load starts from, available to
+    /// templates as `{watermark.<key>}`.
+    pub fn watermark(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.watermarks.insert(key.into(), value.into());
+        self
+    }
+
+    /// Process the source in batches of about `batch_size` rows instead of
+    /// one frame. Stages run per batch, so frame-wide checks (unique, row
+    /// count) and SQL aggregates (`GROUP BY`, `DISTINCT`, windows) only see
+    /// one batch at a time.
+    pub fn streaming(mut self, batch_size: usize) -> Self {
+        let options = self.streaming.get_or_insert_with(StreamOptions::default);
+        options.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Batches buffered between source, stages and sink in streaming mode.
+    pub fn channel_capacity(mut self, capacity: usize) -> Self {
+        let options = self.streaming.get_or_insert_with(StreamOptions::default);
+        options.channel_capacity = capacity.max(1);
+        self
+    }
+
+    pub fn operation<F>(self, op: F) -> Self
     where
         F: Fn(&mut DataFrame) -> Result<DataFrame> + Send + Sync + 'a,
     {
-        self.operations.push(Box::new(op));
+        let name = format!("operation_{}", self.stages.len() + 1);
+        self.named_operation(name, op)
+    }
+
+    /// Like [`operation`](Self::operation), but reported under `name` in spans and logs.
+    pub fn named_operation<F>(mut self, name: impl Into<Cow<'a, str>>, op: F) -> Self
+    where
+        F: Fn(&mut DataFrame) -> Result<DataFrame> + Send + Sync + 'a,
+    {
+        self.stages.push(Stage::Operation {
+            name: name.into(),
+            operation: Arc::new(op),
+        });
+        self
+    }
+
+    /// Add an operation over a `LazyFrame`. Consecutive lazy operations (and
+    /// SQL stages) are collected once, with filters and projections pushed
+    /// down into file scans.
+    pub fn lazy_operation<F>(self, op: F) -> Self
+    where
+        F: Fn(LazyFrame) -> Result<LazyFrame> + Send + Sync + 'a,
+    {
+        let name = format!("operation_{}", self.stages.len() + 1);
+        self.named_lazy_operation(name, op)
+    }
+
+    pub fn named_lazy_operation<F>(mut self, name: impl Into<Cow<'a, str>>, op: F) -> Self
+    where
+        F: Fn(LazyFrame) -> Result<LazyFrame> + Send + Sync + 'a,
+    {
+        self.stages.push(Stage::Lazy {
+            name: name.into(),
+            operation: Arc::new(op),
+        });
+        self
+    }
+
+    /// Add a data quality check; it runs after the stages added before it.
+    pub fn check(mut self, check: Check) -> Self {
+        self.stages.push(Stage::Check(check));
+        self
+    }
+
+    /// Add a SQL statement run against the current frame.
+    pub fn sql(mut self, transform: SqlTransform<'a>) -> Self {
+        self.stages.push(Stage::Sql(transform));
         self
     }
     
     pub fn build(self) -> Result<Pipeline<'a>> {
+        if self.sinks.is_empty() {
+            return Err(Error::Polars("Sink required".to_string()));
+        }
         Ok(Pipeline {
+            name: self.name,
             source: self.source.ok_or_else(|| Error::Polars("Source required".to_string()))?,
-            sink: self.sink.ok_or_else(|| Error::Polars("Source required".to_string()))?,
-            operations: self.operations,
+            sinks: self.sinks,
+            sink_policy: self.sink_policy,
+            quarantine: self.quarantine,
+            stages: self.stages,
+            contract: self.contract,
+            streaming: self.streaming,
+            dry_run: self.dry_run,
+            params: self.params,
+            watermarks: self.watermarks,
         })
     }
 }
 
+/// The frame between stages.
+enum Frame {
+    Lazy(Box<LazyFrame>),
+    Eager(DataFrame),
+}
+
+impl Frame {
+    fn into_lazy(self) -> LazyFrame {
+        match self {
+            Frame::Lazy(lf) => *lf,
+            Frame::Eager(df) => df.lazy(),
+        }
+    }
+
+    /// Materialize the frame. The first materialization sets `rows_in`, so for
+    /// scanned sources it counts rows read after pushdown.
+    async fn collect(self, rows_in: &mut Option<usize>) -> Result<DataFrame> {
+        let df = match self {
+            Frame::Lazy(lf) => collect_lazy(*lf).instrument(info_span!("plan.collect")).await?,
+            Frame::Eager(df) => df,
+        };
+        rows_in.get_or_insert(df.height());
+        Ok(df)
+    }
+}
+
 pub struct Pipeline<'a> {
-    source: SourceKind<'a>,
-    sink: Sinker<'a>,
-    operations: Vec<Box<dyn Fn(&mut DataFrame) -> Result<DataFrame> + Send + Sync + 'a>>,
+    name: Cow<'a, str>,
+    source: SharedSource<'a>,
+    sinks: Vec<SharedSink<'a>>,
+    sink_policy: SinkPolicy,
+    quarantine: Option<SharedSink<'a>>,
+    stages: Vec<Stage<'a>>,
+    contract: Option<SchemaContract>,
+    streaming: Option<StreamOptions>,
+    dry_run: bool,
+    params: BTreeMap<String, String>,
+    watermarks: BTreeMap<String, String>,
 }
 
 impl<'a> Pipeline<'a> {
@@ -58,15 +313,539 @@ impl<'a> Pipeline<'a> {
         PipelineBuilder::new()
     }
 
-    pub async fn run(&self) -> Result<()> {
-        let mut df = self.source.load_data().await?;
-        
-        for (i, operation) in self.operations.iter().enumerate() {
-            tracing::debug!("Applying operation {}", i + 1);
-            df = operation(&mut df)?;
+    pub async fn run(&self) -> Result<RunReport> {
+        let (report, result) = self.run_with_report().await;
+        result.map(|()| report)
+    }
+
+    /// Like [`run`](Self::run), but a failed run still returns its report,
+    /// with the checks, sinks and row counts it got through and `error` set.
+    pub async fn run_with_report(&self) -> (RunReport, Result<()>) {
+        let mut report = RunReport::new(self.name.as_ref());
+        let mut context = RunContext::from_report(&report);
+        context.params = self.params.clone();
+        context.watermarks = self.watermarks.clone();
+        let span = info_span!(
+            "pipeline.run",
+            pipeline = %self.name,
+            run_id = field::Empty,
+            dry_run = self.dry_run,
+            rows_in = field::Empty,
+            rows_out = field::Empty,
+        );
+        let run = async {
+            self.start_report(&mut report);
+            let result = match self.streaming {
+                Some(options) => self.run_streaming(&mut report, options).await,
+                None => self.run_stages(&mut report).await,
+            };
+            if let Err(e) = &result {
+                report.error = Some(e.to_string());
+            }
+            self.finish_report(&mut report);
+            result
+        };
+        let result = context.scope(run.instrument(span)).await;
+        (report, result)
+    }
+
+    async fn run_stages(&self, report: &mut RunReport) -> Result<()> {
+
+        // Stays lazy across lazy stages; collected once an eager stage needs it.
+        let frame = Frame::Lazy(Box::new(self.source.scan().await?));
+        let mut rejected = Vec::new();
+        let (df, rows_in) = self
+            .apply_stages(frame, &[], &report.run_id, &mut report.checks, &mut rejected)
+            .await?;
+        report.rows_in = rows_in;
+
+        if self.dry_run {
+            report.plans = self.plan_all(&self.sinks, &df).await?;
+        } else {
+            report.sinks = fanout::save_all(&self.sinks, self.sink_policy, &df).await?;
         }
-        
-        self.sink.save_data(&mut df).await?;
+        report.rows_out = df.height();
+
+        self.save_rejected(rejected, report).await
+    }
+
+    /// Bounded-memory mode: the source sends batches through a bounded
+    /// channel, every stage runs per batch, and the sinks write each batch as
+    /// it arrives, as does the quarantine sink with each batch's rejects. A
+    /// full channel blocks the upstream side (backpressure).
+    async fn run_streaming(&self, report: &mut RunReport, options: StreamOptions) -> Result<()> {
+        let (source_tx, mut source_rx) = mpsc::channel(options.channel_capacity);
+        let (sink_tx, sink_rx) = mpsc::channel(options.channel_capacity);
+        let (reject_tx, reject_rx) = mpsc::channel(options.channel_capacity);
+
+        let produce = self
+            .source
+            .stream(options.batch_size, source_tx)
+            .instrument(info_span!("source.stream", source = self.source.kind()));
+        let consume = async {
+            if self.dry_run {
+                Ok((Vec::new(), self.plan_stream(&self.sinks, sink_rx).await?))
+            } else {
+                let sinks = fanout::save_stream_all(
+                    &self.sinks,
+                    self.sink_policy,
+                    options.channel_capacity,
+                    sink_rx,
+                )
+                .await?;
+                Ok::<_, Error>((sinks, Vec::new()))
+            }
+        };
+        let quarantine = self.save_rejected_stream(reject_rx);
+
+        // SQL stages run per batch; their sources are loaded once up front.
+        let sql = try_join_all(self.stages.iter().map(|stage| async move {
+            match stage {
+                Stage::Sql(transform) => transform.load_sources().await.map(Some),
+                _ => Ok(None),
+            }
+        }))
+        .await?;
+
+        // Outside the future so a failed run keeps the checks and row counts
+        // so far.
+        let mut checks: Vec<CheckReport> = Vec::new();
+        let (mut rows_in, mut rows_out) = (0, 0);
+        let run_id = report.run_id.as_str();
+        let process = async {
+            // Owned here so the sinks see the channels close when input ends.
+            let (sink_tx, reject_tx) = (sink_tx, reject_tx);
+            let mut rejected = Vec::new();
+            let mut batches = 0;
+
+            while let Some(batch) = source_rx.recv().await {
+                let span = info_span!("batch", batch = batches, rows_in = batch.height());
+                let mut batch_checks = Vec::new();
+                let applied = self
+                    .apply_stages(Frame::Eager(batch), &sql, run_id, &mut batch_checks, &mut rejected)
+                    .instrument(span)
+                    .await;
+                merge_checks(&mut checks, batch_checks);
+                let (df, n) = applied?;
+                rows_in += n;
+                rows_out += df.height();
+                batches += 1;
+                // Sinks failed if a channel is closed; try_join reports the error.
+                if !rejected.is_empty()
+                    && reject_tx.send(concat_df_diagonal(&rejected)?).await.is_err()
+                {
+                    break;
+                }
+                rejected.clear();
+                if sink_tx.send(df).await.is_err() {
+                    break;
+                }
+            }
+            tracing::debug!("Streamed {} batches", batches);
+            Ok::<_, Error>(())
+        };
+
+        let joined = tokio::try_join!(produce, process, consume, quarantine);
+        report.checks = checks;
+        report.rows_in = rows_in;
+        report.rows_out = rows_out;
+        let ((), (), (sinks, mut plans), quarantine_plans) = joined?;
+        plans.extend(quarantine_plans);
+        report.sinks = sinks;
+        report.plans = plans;
         Ok(())
     }
-}
\ No newline at end of file
+
+    /// Run every stage over `frame`; returns the materialized frame and the
+    /// row count at first materialization. `sql` holds SQL stages with their
+    /// sources already loaded, by stage index; others load their own.
+    async fn apply_stages(
+        &self,
+        mut frame: Frame,
+        sql: &[Option<SqlTransform<'a>>],
+        run_id: &str,
+        checks: &mut Vec<CheckReport>,
+        rejected: &mut Vec<DataFrame>,
+    ) -> Result<(DataFrame, usize)> {
+        let mut rows_in = None;
+
+        for (index, stage) in self.stages.iter().enumerate() {
+            match stage {
+                Stage::Lazy { name, operation } => {
+                    let _span = info_span!("operation", name = %name, lazy = true).entered();
+                    tracing::debug!("Adding lazy operation {} to plan", name);
+                    frame = Frame::Lazy(Box::new(operation(frame.into_lazy())?));
+                }
+                Stage::Sql(transform) => {
+                    let transform = sql.get(index).and_then(Option::as_ref).unwrap_or(transform);
+                    let span = info_span!("operation", name = "sql", query = transform.query());
+                    let lf = transform.run(frame.into_lazy()).instrument(span).await?;
+                    frame = Frame::Lazy(Box::new(lf));
+                }
+                Stage::Operation { name, operation } => {
+                    let mut df = frame.collect(&mut rows_in).await?;
+                    let span = info_span!(
+                        "operation",
+                        name = %name,
+                        rows_in = df.height(),
+                        rows_out = field::Empty,
+                    );
+                    df = span.in_scope(|| {
+                        tracing::debug!("Applying operation {}", name);
+                        operation(&mut df)
+                    })?;
+                    span.record("rows_out", df.height());
+                    frame = Frame::Eager(df);
+                }
+                Stage::Check(check) => {
+                    let df = frame.collect(&mut rows_in).await?;
+                    let span = info_span!(
+                        "check",
+                        name = %check.name(),
+                        rows_in = df.height(),
+                        rows_out = field::Empty,
+                    );
+                    let mut failed = None;
+                    let result = span.in_scope(|| check.apply_reporting(df, &mut failed));
+                    checks.extend(failed);
+                    let result = result?;
+                    span.record("rows_out", result.df.height());
+                    if let Some(rows) = &result.rejected {
+                        if self.quarantine.is_some() {
+                            rejected.push(annotate_rejected(
+                                rows,
+                                &result.report.rule,
+                                check.name(),
+                                run_id,
+                            )?);
+                        } else {
+                            tracing::warn!(
+                                "Dropping {} rows quarantined by {} (no quarantine sink)",
+                                rows.height(),
+                                check.name()
+                            );
+                        }
+                    }
+                    checks.push(result.report);
+                    frame = Frame::Eager(result.df);
+                }
+            }
+        }
+
+        let mut df = frame.collect(&mut rows_in).await?;
+        if let Some(contract) = &self.contract {
+            df = info_span!("contract", columns = contract.columns().len())
+                .in_scope(|| contract.enforce(df))?;
+        }
+        Ok((df, rows_in.unwrap_or_default()))
+    }
+
+    async fn save_rejected(&self, rejected: Vec<DataFrame>, report: &mut RunReport) -> Result<()> {
+        if let (Some(sink), false) = (&self.quarantine, rejected.is_empty()) {
+            let mut rejected = concat_df_diagonal(&rejected)?;
+            if self.dry_run {
+                report.plans.push(sink.plan(&rejected).await?);
+                return Ok(());
+            }
+            let span = info_span!("quarantine.save", sink = sink.kind(), rows = rejected.height());
+            sink.save_data(&mut rejected).instrument(span).await?;
+        }
+        Ok(())
+    }
+
+    /// Streaming counterpart of [`save_rejected`](Self::save_rejected): write
+    /// each batch's rejects as they arrive, or plan them in a dry run.
+    async fn save_rejected_stream(&self, mut rx: mpsc::Receiver<DataFrame>) -> Result<Vec<SinkPlan>> {
+        let Some(sink) = &self.quarantine else {
+            return Ok(Vec::new());
+        };
+        if self.dry_run {
+            let plans = self.plan_stream(std::slice::from_ref(sink), rx).await?;
+            return Ok(plans.into_iter().filter(|plan| plan.rows > 0).collect());
+        }
+        let span = info_span!("quarantine.stream", sink = sink.kind());
+        let rows = async {
+            // Wait for the first reject so sinks that create a file or table
+            // on their first batch don't when nothing is rejected.
+            let Some(first) = rx.recv().await else {
+                return Ok(0);
+            };
+            let (tx, stream_rx) = mpsc::channel(1);
+            let forward = async move {
+                let mut next = Some(first);
+                while let Some(df) = next {
+                    if tx.send(df).await.is_err() {
+                        break;
+                    }
+                    next = rx.recv().await;
+                }
+            };
+            let (rows, ()) = tokio::join!(sink.save_stream(stream_rx), forward);
+            rows
+        };
+        let rows = rows.instrument(span).await?;
+        tracing::debug!("Quarantined {} rows", rows);
+        Ok(Vec::new())
+    }
+
+    fn start_report(&self, report: &mut RunReport) {
+        report.dry_run = self.dry_run;
+        tracing::Span::current().record("run_id", report.run_id.as_str());
+    }
+
+    /// Plan every sink for `df` and log the plans.
+    async fn plan_all(&self, sinks: &[SharedSink<'a>], df: &DataFrame) -> Result<Vec<SinkPlan>> {
+        let mut plans = Vec::with_capacity(sinks.len());
+        for sink in sinks {
+            let plan = sink.plan(df).await?;
+            tracing::info!(
+                "Dry run: {} sink would write {} rows to {}",
+                plan.sink,
+                plan.rows,
+                plan.target
+            );
+            for statement in &plan.statements {
+                tracing::info!("Dry run: {}", statement);
+            }
+            plans.push(plan);
+        }
+        Ok(plans)
+    }
+
+    /// Streaming dry run: count the batches and plan against their schema.
+    async fn plan_stream(
+        &self,
+        sinks: &[SharedSink<'a>],
+        mut rx: mpsc::Receiver<DataFrame>,
+    ) -> Result<Vec<SinkPlan>> {
+        let mut rows = 0;
+        let mut empty = None;
+        while let Some(df) = rx.recv().await {
+            rows += df.height();
+            empty.get_or_insert_with(|| df.clear());
+        }
+        let mut plans = self.plan_all(sinks, &empty.unwrap_or_default()).await?;
+        for plan in &mut plans {
+            plan.rows = rows;
+        }
+        Ok(plans)
+    }
+
+    /// Stamp the end time and record row and duration metrics, for failed
+    /// runs as well.
+    fn finish_report(&self, report: &mut RunReport) {
+        let span = tracing::Span::current();
+        span.record("rows_in", report.rows_in);
+        span.record("rows_out", report.rows_out);
+        report.finished_at_ms = now_ms();
+
+        let m = metrics();
+        let outcome = if report.error.is_none() { "success" } else { "failure" };
+        m.pipeline_duration
+            .with_label_values(&[self.name.as_ref(), outcome])
+            .observe(report.finished_at_ms.saturating_sub(report.started_at_ms) as f64 / 1000.0);
+        m.rows_in
+            .with_label_values(&[self.name.as_ref(), self.source.kind()])
+            .inc_by(report.rows_in as u64);
+        for sink in report.sinks.iter().filter(|s| s.error.is_none()) {
+            m.rows_out
+                .with_label_values(&[self.name.as_ref(), sink.sink.as_str()])
+                .inc_by(sink.rows as u64);
+        }
+    }
+}
+
+/// Fold one batch's check reports into the run totals, stage by stage.
+fn merge_checks(totals: &mut Vec<CheckReport>, batch: Vec<CheckReport>) {
+    if totals.is_empty() {
+        *totals = batch;
+        return;
+    }
+    for (total, report) in totals.iter_mut().zip(batch) {
+        total.passed &= report.passed;
+        total.failing_rows += report.failing_rows;
+        total.quarantined_rows += report.quarantined_rows;
+        if report.message.is_some() {
+            total.message = report.message;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    };
+
+    use async_trait::async_trait;
+    use polars::prelude::*;
+
+    use super::*;
+
+    /// An in-memory source.
+    struct Frame(DataFrame);
+
+    #[async_trait]
+    impl Source for Frame {
+        async fn load_data(&self) -> Result<DataFrame> {
+            Ok(self.0.clone())
+        }
+    }
+
+    /// A sink that keeps every frame it is given.
+    #[derive(Clone, Default)]
+    struct Collect(Arc<Mutex<Vec<DataFrame>>>);
+
+    #[async_trait]
+    impl Sink for Collect {
+        async fn save_data(&self, df: &mut DataFrame) -> Result<()> {
+            self.0.lock().unwrap().push(df.clone());
+            Ok(())
+        }
+    }
+
+    impl Collect {
+        fn heights(&self) -> Vec<usize> {
+            self.0.lock().unwrap().iter().map(DataFrame::height).collect()
+        }
+    }
+
+    /// A source that counts its loads.
+    #[derive(Default)]
+    struct Counted(AtomicUsize);
+
+    #[async_trait]
+    impl Source for Counted {
+        async fn load_data(&self) -> Result<DataFrame> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(df!("id" => [1i64, 2], "name" => ["one", "two"])?)
+        }
+    }
+
+    fn ids_with_nulls() -> DataFrame {
+        let ids: Vec<Option<i64>> = (0..1_000).map(|i| (i % 10 != 0).then_some(i)).collect();
+        df!("id" => ids).unwrap()
+    }
+
+    #[tokio::test]
+    async fn streaming_quarantines_each_batch() {
+        let (out, rejected) = (Collect::default(), Collect::default());
+        let report = Pipeline::builder()
+            .source(Frame(ids_with_nulls()))
+            .check(Check::not_null("id").quarantine())
+            .sink(out.clone())
+            .quarantine(rejected.clone())
+            .streaming(100)
+            .build()
+            .unwrap()
+            .run()
+            .await
+            .unwrap();
+
+        assert_eq!(out.heights(), vec![90; 10]);
+        assert_eq!(rejected.heights(), vec![10; 10]);
+        assert_eq!(report.checks[0].quarantined_rows, 100);
+        let first = &rejected.0.lock().unwrap()[0];
+        assert_eq!(first.column("_stage").unwrap().str().unwrap().get(0), Some("not_null(id)"));
+    }
+
+    #[tokio::test]
+    async fn streaming_without_rejects_leaves_quarantine_untouched() {
+        let rejected = Collect::default();
+        Pipeline::builder()
+            .source(Frame(df!("id" => (0..1_000i64).collect::<Vec<_>>()).unwrap()))
+            .check(Check::not_null("id").quarantine())
+            .sink(Collect::default())
+            .quarantine(rejected.clone())
+            .streaming(100)
+            .build()
+            .unwrap()
+            .run()
+            .await
+            .unwrap();
+
+        assert!(rejected.heights().is_empty());
+    }
+
+    #[tokio::test]
+    async fn streaming_loads_sql_sources_once() {
+        let lookup = Arc::new(Counted::default());
+        let out = Collect::default();
+        Pipeline::builder()
+            .source(Frame(df!("id" => (0..1_000i64).collect::<Vec<_>>()).unwrap()))
+            .sql(
+                SqlTransform::new("SELECT input.id, name FROM input JOIN names USING (id)")
+                    .shared_source("names", lookup.clone()),
+            )
+            .sink(out.clone())
+            .streaming(100)
+            .build()
+            .unwrap()
+            .run()
+            .await
+            .unwrap();
+
+        assert_eq!(lookup.0.load(Ordering::SeqCst), 1);
+        assert_eq!(out.heights().iter().sum::<usize>(), 2);
+    }
+
+    fn check_report(
+        name: &str,
+        passed: bool,
+        failing: usize,
+        message: Option<&str>,
+    ) -> CheckReport {
+        CheckReport {
+            name: name.to_string(),
+            rule: name.to_string(),
+            severity: crate::quality::Severity::Quarantine,
+            passed,
+            failing_rows: failing,
+            quarantined_rows: failing,
+            message: message.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn merge_checks_sums_batches_stage_by_stage() {
+        let mut totals = Vec::new();
+        merge_checks(
+            &mut totals,
+            vec![
+                check_report("a", true, 0, None),
+                check_report("b", false, 2, Some("2 rows")),
+            ],
+        );
+        merge_checks(
+            &mut totals,
+            vec![
+                check_report("a", false, 3, Some("3 rows")),
+                check_report("b", true, 0, None),
+            ],
+        );
+
+        assert_eq!(totals.len(), 2);
+        assert!(!totals[0].passed && !totals[1].passed);
+        assert_eq!((totals[0].failing_rows, totals[0].quarantined_rows), (3, 3));
+        assert_eq!((totals[1].failing_rows, totals[1].quarantined_rows), (2, 2));
+        // The latest failure message wins; a passing batch keeps the last one.
+        assert_eq!(totals[0].message.as_deref(), Some("3 rows"));
+        assert_eq!(totals[1].message.as_deref(), Some("2 rows"));
+    }
+
+    #[test]
+    fn merge_checks_keeps_a_failed_batch_that_stopped_early() {
+        let mut totals = vec![
+            check_report("a", true, 0, None),
+            check_report("b", true, 0, None),
+        ];
+        merge_checks(
+            &mut totals,
+            vec![check_report("a", false, 1, Some("failed"))],
+        );
+        assert_eq!(totals.len(), 2);
+        assert!(!totals[0].passed);
+        assert!(totals[1].passed);
+    }
+}
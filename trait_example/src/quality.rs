@@ -0,0 +1,572 @@
+use std::{collections::HashSet, sync::Arc, time::Duration};
+
+use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::errors::{Error, Result};
+use crate::reports::now_ms;
+use crate::schema::parse_dtype;
+use crate::sqlite::to_json;
+
+// ============================================================================
+// Rules
+// ============================================================================
+
+/// What a check does when rows (or the frame) violate its rule.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    /// Log and keep going.
+    Warn,
+    /// Abort the run with `Error::Quality`.
+    #[default]
+    Fail,
+    /// Drop the offending rows and keep going. Frame-level rules (row count,
+    /// freshness) have no offending rows, so they fail instead.
+    Quarantine,
+}
+
+#[derive(Clone, Debug)]
+pub enum Rule {
+    NotNull { column: String },
+    Unique { columns: Vec<String> },
+    AcceptedValues { column: String, values: Arc<HashSet<String>> },
+    Regex { column: String, pattern: String },
+    Range { column: String, min: Option<f64>, max: Option<f64> },
+    RowCount { min: Option<usize>, max: Option<usize> },
+    Freshness { column: String, max_age: Duration },
+    /// Every value must exist in a reference column, e.g. keys of another table.
+    Membership { column: String, reference: Arc<HashSet<String>> },
+    /// Cast `column` to `dtype`; values that do not survive the cast violate the rule.
+    Cast { column: String, dtype: DataType },
+}
+
+impl Rule {
+    fn describe(&self) -> String {
+        match self {
+            Rule::NotNull { column } => format!("not_null({column})"),
+            Rule::Unique { columns } => format!("unique({})", columns.join(", ")),
+            Rule::AcceptedValues { column, values } => {
+                format!("accepted_values({column}, {} values)", values.len())
+            }
+            Rule::Regex { column, pattern } => format!("regex({column}, /{pattern}/)"),
+            Rule::Range { column, min, max } => format!("range({column}, {})", bounds(min, max)),
+            Rule::RowCount { min, max } => format!("row_count({})", bounds(min, max)),
+            Rule::Freshness { column, max_age } => format!("freshness({column}, {max_age:?})"),
+            Rule::Membership { column, reference } => {
+                format!("membership({column}, {} keys)", reference.len())
+            }
+            Rule::Cast { column, dtype } => format!("cast({column} as {dtype})"),
+        }
+    }
+}
+
+/// Render optional bounds as `lo..=hi`, `lo..` or `..=hi`.
+fn bounds<T: std::fmt::Display>(min: &Option<T>, max: &Option<T>) -> String {
+    let lo = min.as_ref().map(|v| v.to_string()).unwrap_or_default();
+    match max {
+        Some(hi) => format!("{lo}..={hi}"),
+        None => format!("{lo}.."),
+    }
+}
+
+/// A declarative data quality check, usable as a pipeline stage.
+#[derive(Clone, Debug)]
+pub struct Check {
+    name: String,
+    rule: Rule,
+    severity: Severity,
+}
+
+impl Check {
+    /// Wrap a rule; fails the run by default.
+    pub fn new(rule: Rule) -> Self {
+        Self {
+            name: rule.describe(),
+            rule,
+            severity: Severity::Fail,
+        }
+    }
+
+    pub fn not_null(column: impl Into<String>) -> Self {
+        Self::new(Rule::NotNull {
+            column: column.into(),
+        })
+    }
+
+    pub fn unique<S: Into<String>>(columns: impl IntoIterator<Item = S>) -> Self {
+        Self::new(Rule::Unique {
+            columns: columns.into_iter().map(Into::into).collect(),
+        })
+    }
+
+    pub fn accepted_values<S: Into<String>>(
+        column: impl Into<String>,
+        values: impl IntoIterator<Item = S>,
+    ) -> Self {
+        Self::new(Rule::AcceptedValues {
+            column: column.into(),
+            values: Arc::new(values.into_iter().map(Into::into).collect()),
+        })
+    }
+
+    pub fn regex(column: impl Into<String>, pattern: impl Into<String>) -> Self {
+        Self::new(Rule::Regex {
+            column: column.into(),
+            pattern: pattern.into(),
+        })
+    }
+
+    pub fn range(column: impl Into<String>, min: Option<f64>, max: Option<f64>) -> Self {
+        Self::new(Rule::Range {
+            column: column.into(),
+            min,
+            max,
+        })
+    }
+
+    pub fn row_count(min: Option<usize>, max: Option<usize>) -> Self {
+        Self::new(Rule::RowCount { min, max })
+    }
+
+    pub fn freshness(column: impl Into<String>, max_age: Duration) -> Self {
+        Self::new(Rule::Freshness {
+            column: column.into(),
+            max_age,
+        })
+    }
+
+    /// Values of `column` must appear in `reference` (nulls are ignored).
+    pub fn membership(column: impl Into<String>, reference: &Column) -> Result<Self> {
+        Ok(Self::new(Rule::Membership {
+            column: column.into(),
+            reference: Arc::new(string_values(reference)?.into_iter().flatten().collect()),
+        }))
+    }
+
+    /// Cast `column` to `dtype`, treating values that become null as violations.
+    ///
+    /// Unlike the other rules this changes the frame: passing and warned rows
+    /// continue with the cast column.
+    pub fn cast(column: impl Into<String>, dtype: DataType) -> Self {
+        Self::new(Rule::Cast {
+            column: column.into(),
+            dtype,
+        })
+    }
+
+    pub fn named(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    pub fn warn(mut self) -> Self {
+        self.severity = Severity::Warn;
+        self
+    }
+
+    pub fn fail(mut self) -> Self {
+        self.severity = Severity::Fail;
+        self
+    }
+
+    pub fn quarantine(mut self) -> Self {
+        self.severity = Severity::Quarantine;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    /// Evaluate the rule and apply the severity.
+    ///
+    /// Returns the frame to continue with, the rows that were quarantined (if
+    /// any) and the report entry.
+    pub fn apply(&self, df: DataFrame) -> Result<CheckResult> {
+        self.apply_reporting(df, &mut None)
+    }
+
+    /// [`apply`](Self::apply), keeping the report of a check that fails the
+    /// run in `failed` so the run history still records it.
+    pub(crate) fn apply_reporting(
+        &self,
+        df: DataFrame,
+        failed: &mut Option<CheckReport>,
+    ) -> Result<CheckResult> {
+        let violation = self.evaluate(&df)?;
+        let mut result = self.decide(df, violation, failed)?;
+
+        if let Rule::Cast { column, dtype } = &self.rule {
+            let cast = result.df.column(column)?.cast(dtype)?;
+            result.df.with_column(cast)?;
+        }
+        Ok(result)
+    }
+
+    fn decide(
+        &self,
+        df: DataFrame,
+        violation: Option<Violation>,
+        failed: &mut Option<CheckReport>,
+    ) -> Result<CheckResult> {
+        let mut report = CheckReport {
+            name: self.name.clone(),
+            rule: self.rule.describe(),
+            severity: self.severity,
+            passed: violation.is_none(),
+            failing_rows: 0,
+            quarantined_rows: 0,
+            message: None,
+        };
+
+        let Some(violation) = violation else {
+            return Ok(CheckResult {
+                df,
+                rejected: None,
+                report,
+            });
+        };
+
+        let message = match &violation {
+            Violation::Rows(mask) => {
+                report.failing_rows = mask.sum().unwrap_or(0) as usize;
+                format!("{} rows failed {}", report.failing_rows, report.rule)
+            }
+            Violation::Frame(message) => message.clone(),
+        };
+        report.message = Some(message.clone());
+
+        match (self.severity, violation) {
+            (Severity::Warn, _) => {
+                tracing::warn!("check {} failed: {}", self.name, message);
+                Ok(CheckResult {
+                    df,
+                    rejected: None,
+                    report,
+                })
+            }
+            (Severity::Quarantine, Violation::Rows(mask)) => {
+                tracing::warn!("check {} quarantined rows: {}", self.name, message);
+                let rejected = df.filter(&mask)?;
+                let kept = df.filter(&!&mask)?;
+                report.quarantined_rows = rejected.height();
+                Ok(CheckResult {
+                    df: kept,
+                    rejected: Some(rejected),
+                    report,
+                })
+            }
+            (Severity::Fail, _) | (Severity::Quarantine, Violation::Frame(_)) => {
+                *failed = Some(report);
+                Err(Error::Quality(format!("check {} failed: {}", self.name, message)))
+            }
+        }
+    }
+
+    fn evaluate(&self, df: &DataFrame) -> Result<Option<Violation>> {
+        let rows = |mask: BooleanChunked| {
+            if mask.any() {
+                Some(Violation::Rows(mask))
+            } else {
+                None
+            }
+        };
+
+        let violation = match &self.rule {
+            Rule::NotNull { column } => rows(df.column(column)?.is_null()),
+
+            Rule::Unique { columns } => rows(df.select(columns.iter().cloned())?.is_duplicated()?),
+
+            Rule::AcceptedValues { column, values: set }
+            | Rule::Membership {
+                column,
+                reference: set,
+            } => {
+                let mask: BooleanChunked = string_values(df.column(column)?)?
+                    .into_iter()
+                    .map(|v| v.is_some_and(|v| !set.contains(&v)))
+                    .collect();
+                rows(mask.with_name(column.as_str().into()))
+            }
+
+            Rule::Regex { column, pattern } => {
+                let s = df.column(column)?.cast(&DataType::String)?;
+                let matches = s.str()?.contains(pattern, true)?;
+                let mask: BooleanChunked = matches
+                    .into_iter()
+                    .map(|m| m == Some(false))
+                    .collect();
+                rows(mask.with_name(column.as_str().into()))
+            }
+
+            Rule::Range { column, min, max } => {
+                let s = df.column(column)?.cast(&DataType::Float64)?;
+                let mask: BooleanChunked = s
+                    .f64()?
+                    .into_iter()
+                    .map(|v| {
+                        v.is_some_and(|v| {
+                            min.is_some_and(|min| v < min) || max.is_some_and(|max| v > max)
+                        })
+                    })
+                    .collect();
+                rows(mask.with_name(column.as_str().into()))
+            }
+
+            Rule::RowCount { min, max } => {
+                let n = df.height();
+                if min.is_some_and(|min| n < min) || max.is_some_and(|max| n > max) {
+                    Some(Violation::Frame(format!(
+                        "row count {n} outside {}",
+                        bounds(min, max)
+                    )))
+                } else {
+                    None
+                }
+            }
+
+            Rule::Freshness { column, max_age } => {
+                let s = df
+                    .column(column)?
+                    .cast(&DataType::Datetime(TimeUnit::Milliseconds, None))?;
+                let newest = s.datetime()?.physical().max();
+                let cutoff = now_ms() as i64 - max_age.as_millis() as i64;
+                match newest {
+                    Some(ts) if ts >= cutoff => None,
+                    Some(ts) => Some(Violation::Frame(format!(
+                        "newest {column} is {}s old, max age {max_age:?}",
+                        (now_ms() as i64 - ts) / 1000
+                    ))),
+                    None => Some(Violation::Frame(format!("{column} has no values"))),
+                }
+            }
+
+            Rule::Cast { column, dtype } => {
+                let original = df.column(column)?;
+                let cast = original.cast(dtype)?;
+                let mask = original.is_not_null() & cast.is_null();
+                rows(mask.with_name(column.as_str().into()))
+            }
+        };
+
+        Ok(violation)
+    }
+}
+
+enum Violation {
+    /// Mask of offending rows.
+    Rows(BooleanChunked),
+    /// The frame as a whole violates the rule.
+    Frame(String),
+}
+
+/// Column values as optional strings, for set-membership rules.
+fn string_values(column: &Column) -> Result<Vec<Option<String>>> {
+    let s = column.cast(&DataType::String)?;
+    Ok(s.str()?
+        .into_iter()
+        .map(|v| v.map(str::to_string))
+        .collect())
+}
+
+/// Tag rejected rows for a quarantine sink.
+///
+/// Payload columns are stringified, nested ones as JSON text, so rejects
+/// from different stages (e.g. before and after a cast) share one schema.
+pub fn annotate_rejected(
+    rejected: &DataFrame,
+    rule: &str,
+    stage: &str,
+    run_id: &str,
+) -> Result<DataFrame> {
+    let n = rejected.height();
+    let mut columns: Vec<Column> = rejected
+        .get_columns()
+        .iter()
+        .map(|c| match c.dtype().is_nested() {
+            true => Ok(json_column(c)),
+            false => c.cast(&DataType::String),
+        })
+        .collect::<PolarsResult<_>>()?;
+    columns.push(Column::new_scalar("_rule".into(), Scalar::from(PlSmallStr::from(rule)), n));
+    columns.push(Column::new_scalar("_stage".into(), Scalar::from(PlSmallStr::from(stage)), n));
+    columns.push(Column::new_scalar("_run_id".into(), Scalar::from(PlSmallStr::from(run_id)), n));
+    Ok(DataFrame::new(columns)?)
+}
+
+/// A nested column as JSON text, nulls kept.
+fn json_column(column: &Column) -> Column {
+    let values: StringChunked = column
+        .as_materialized_series()
+        .iter()
+        .map(|v| (!v.is_null()).then(|| to_json(v).to_string()))
+        .collect();
+    values.with_name(column.name().clone()).into_column()
+}
+
+// ============================================================================
+// Results
+// ============================================================================
+
+/// Outcome of one check, recorded in the run report.
+#[derive(Clone, Debug, Serialize)]
+pub struct CheckReport {
+    pub name: String,
+    pub rule: String,
+    pub severity: Severity,
+    pub passed: bool,
+    pub failing_rows: usize,
+    pub quarantined_rows: usize,
+    pub message: Option<String>,
+}
+
+pub struct CheckResult {
+    pub df: DataFrame,
+    pub rejected: Option<DataFrame>,
+    pub report: CheckReport,
+}
+
+// ============================================================================
+// Config
+// ============================================================================
+
+/// A check stage in a job file. `membership` needs a reference column and is
+/// only available in code.
+///
+/// ```json
+/// { "kind": "check", "rule": "not_null", "column": "id", "severity": "quarantine" }
+/// ```
+#[derive(Clone, Debug, Deserialize)]
+pub struct CheckConfig {
+    pub name: Option<String>,
+    #[serde(default)]
+    pub severity: Severity,
+    #[serde(flatten)]
+    pub rule: RuleConfig,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "rule", rename_all = "snake_case")]
+pub enum RuleConfig {
+    NotNull { column: String },
+    Unique { columns: Vec<String> },
+    AcceptedValues { column: String, values: Vec<String> },
+    Regex { column: String, pattern: String },
+    Range { column: String, min: Option<f64>, max: Option<f64> },
+    RowCount { min: Option<usize>, max: Option<usize> },
+    Freshness { column: String, max_age_secs: u64 },
+    /// `dtype` as in a contract, e.g. `int64` or `datetime[ms]`.
+    Cast { column: String, dtype: String },
+}
+
+impl CheckConfig {
+    pub fn into_check(self) -> Result<Check> {
+        let check = match self.rule {
+            RuleConfig::NotNull { column } => Check::not_null(column),
+            RuleConfig::Unique { columns } => Check::unique(columns),
+            RuleConfig::AcceptedValues { column, values } => Check::accepted_values(column, values),
+            RuleConfig::Regex { column, pattern } => Check::regex(column, pattern),
+            RuleConfig::Range { column, min, max } => Check::range(column, min, max),
+            RuleConfig::RowCount { min, max } => Check::row_count(min, max),
+            RuleConfig::Freshness {
+                column,
+                max_age_secs,
+            } => Check::freshness(column, Duration::from_secs(max_age_secs)),
+            RuleConfig::Cast { column, dtype } => Check::cast(column, parse_dtype(&dtype)?),
+        };
+        let check = match self.severity {
+            Severity::Warn => check.warn(),
+            Severity::Fail => check.fail(),
+            Severity::Quarantine => check.quarantine(),
+        };
+        Ok(match self.name {
+            Some(name) => check.named(name),
+            None => check,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use polars::functions::concat_df_diagonal;
+
+    use super::*;
+
+    #[test]
+    fn annotate_rejected_stringifies_nested_columns() {
+        let list = Series::new(
+            "tags".into(),
+            [Series::new("".into(), [1i64, 2]), Series::new("".into(), [3i64])],
+        );
+        let nested = df!("id" => [1i64, 2], "tags" => list).unwrap();
+        let nested = nested
+            .lazy()
+            .with_column(as_struct(vec![col("id"), col("tags")]).alias("row"))
+            .collect()
+            .unwrap();
+        let flat = df!("id" => ["x"], "tags" => ["[]"], "row" => ["{}"]).unwrap();
+
+        let nested = annotate_rejected(&nested, "rule", "stage", "run").unwrap();
+        assert_eq!(nested.column("tags").unwrap().dtype(), &DataType::String);
+        assert_eq!(nested.column("tags").unwrap().str().unwrap().get(0), Some("[1,2]"));
+        assert_eq!(
+            nested.column("row").unwrap().str().unwrap().get(1),
+            Some(r#"{"id":2,"tags":[3]}"#)
+        );
+
+        let flat = annotate_rejected(&flat, "rule", "stage", "run").unwrap();
+        let all = concat_df_diagonal(&[nested, flat]).unwrap();
+        assert_eq!(all.height(), 3);
+    }
+
+    fn sample() -> DataFrame {
+        df!(
+            "id" => [Some(1i64), Some(2), None, Some(2)],
+            "score" => [0.5f64, 1.5, 0.2, 0.9],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn quarantine_splits_failing_rows() {
+        let result = Check::not_null("id").quarantine().apply(sample()).unwrap();
+        assert_eq!(result.df.height(), 3);
+        assert_eq!(result.rejected.unwrap().height(), 1);
+        assert!(!result.report.passed);
+        assert_eq!(result.report.failing_rows, 1);
+        assert_eq!(result.report.quarantined_rows, 1);
+    }
+
+    #[test]
+    fn warn_keeps_every_row() {
+        let result = Check::range("score", Some(0.0), Some(1.0))
+            .warn()
+            .apply(sample())
+            .unwrap();
+        assert_eq!(result.df.height(), 4);
+        assert!(result.rejected.is_none());
+        assert_eq!(result.report.failing_rows, 1);
+    }
+
+    #[test]
+    fn fail_stops_the_run_and_keeps_the_report() {
+        let mut failed = None;
+        let check = Check::unique(["id"]);
+        assert!(check.apply_reporting(sample(), &mut failed).is_err());
+        let report = failed.unwrap();
+        assert!(!report.passed);
+        assert_eq!(report.failing_rows, 2);
+    }
+
+    #[test]
+    fn row_count_checks_the_whole_frame() {
+        let result = Check::row_count(Some(1), Some(10)).apply(sample()).unwrap();
+        assert!(result.report.passed);
+        assert!(Check::row_count(Some(5), None).apply(sample()).is_err());
+    }
+}
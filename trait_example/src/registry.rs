@@ -0,0 +1,79 @@
+use std::{collections::HashMap, sync::Arc};
+
+use serde_json::Value;
+
+use crate::errors::{Error, Result};
+use crate::sinks::SharedSink;
+use crate::sources::SharedSource;
+
+/// Builds a source from the parameters of a config entry (the object
+/// without its `kind` field).
+pub type SourceFactory = Arc<dyn Fn(Value) -> Result<SharedSource<'static>> + Send + Sync>;
+
+/// Builds a sink from the parameters of a config entry.
+pub type SinkFactory = Arc<dyn Fn(Value) -> Result<SharedSink<'static>> + Send + Sync>;
+
+/// Custom source and sink kinds that job configs can refer to by name.
+///
+/// ```ignore
+/// let registry = Registry::new().source("warehouse", |params| {
+///     let config: WarehouseConfig = serde_json::from_value(params)?;
+///     Ok(Arc::new(WarehouseSource::new(config)))
+/// });
+/// let job = JobConfig::from_path("job.json")?.into_job_with(&registry)?;
+/// ```
+#[derive(Clone, Default)]
+pub struct Registry {
+    sources: HashMap<String, SourceFactory>,
+    sinks: HashMap<String, SinkFactory>,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a source kind. Built-in kinds (`http`, `csv`, ...) always
+    /// take precedence over registered ones.
+    pub fn source<F>(mut self, kind: impl Into<String>, factory: F) -> Self
+    where
+        F: Fn(Value) -> Result<SharedSource<'static>> + Send + Sync + 'static,
+    {
+        self.sources.insert(kind.into(), Arc::new(factory));
+        self
+    }
+
+    /// Register a sink kind.
+    pub fn sink<F>(mut self, kind: impl Into<String>, factory: F) -> Self
+    where
+        F: Fn(Value) -> Result<SharedSink<'static>> + Send + Sync + 'static,
+    {
+        self.sinks.insert(kind.into(), Arc::new(factory));
+        self
+    }
+
+    pub fn build_source(&self, kind: &str, params: Value) -> Result<SharedSource<'static>> {
+        let factory = self
+            .sources
+            .get(kind)
+            .ok_or_else(|| Error::Config(format!("unknown source kind '{kind}'")))?;
+        factory(params)
+    }
+
+    pub fn build_sink(&self, kind: &str, params: Value) -> Result<SharedSink<'static>> {
+        let factory = self
+            .sinks
+            .get(kind)
+            .ok_or_else(|| Error::Config(format!("unknown sink kind '{kind}'")))?;
+        factory(params)
+    }
+}
+
+impl std::fmt::Debug for Registry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Registry")
+            .field("sources", &self.sources.keys().collect::<Vec<_>>())
+            .field("sinks", &self.sinks.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
@@ -0,0 +1,83 @@
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::Serialize;
+
+use crate::errors::Result;
+use crate::quality::CheckReport;
+use crate::sinks::{SinkPlan, UpsertCounts};
+
+/// Milliseconds since the Unix epoch.
+pub fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// What happened during one `Pipeline::run`.
+#[derive(Clone, Debug, Serialize)]
+pub struct RunReport {
+    pub run_id: String,
+    pub pipeline: String,
+    pub started_at_ms: u64,
+    pub finished_at_ms: u64,
+    pub rows_in: usize,
+    pub rows_out: usize,
+    pub checks: Vec<CheckReport>,
+    pub sinks: Vec<SinkReport>,
+    pub dry_run: bool,
+    /// What each sink (then the quarantine sink) would have written, for dry runs.
+    pub plans: Vec<SinkPlan>,
+    /// Why the run failed; the other fields hold what it got through.
+    pub error: Option<String>,
+}
+
+/// Outcome of writing to one sink.
+#[derive(Clone, Debug, Serialize)]
+pub struct SinkReport {
+    /// Position of the sink in the pipeline.
+    pub index: usize,
+    pub sink: String,
+    pub rows: usize,
+    pub duration_ms: u64,
+    pub error: Option<String>,
+    /// Row counts for sinks that upsert, summed over streamed batches.
+    pub upserts: Option<UpsertCounts>,
+}
+
+impl RunReport {
+    pub fn new(pipeline: impl Into<String>) -> Self {
+        Self {
+            run_id: uuid::Uuid::new_v4().to_string(),
+            pipeline: pipeline.into(),
+            started_at_ms: now_ms(),
+            finished_at_ms: 0,
+            rows_in: 0,
+            rows_out: 0,
+            checks: Vec::new(),
+            sinks: Vec::new(),
+            dry_run: false,
+            plans: Vec::new(),
+            error: None,
+        }
+    }
+
+    /// Rows dropped by quarantining checks.
+    pub fn quarantined_rows(&self) -> usize {
+        self.checks.iter().map(|c| c.quarantined_rows).sum()
+    }
+
+    /// Append this report as one JSON line to a run-history file.
+    pub fn append_to(&self, path: impl AsRef<Path>) -> Result<()> {
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        let mut line = serde_json::to_vec(self)?;
+        line.push(b'\n');
+        file.write_all(&line)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,324 @@
+use std::fmt;
+
+use polars::prelude::*;
+use serde::Deserialize;
+
+use crate::errors::{Error, Result};
+
+/// One expected output column.
+#[derive(Clone, Debug)]
+pub struct ColumnSpec {
+    pub name: String,
+    pub dtype: DataType,
+    pub nullable: bool,
+}
+
+/// The schema a pipeline promises its sinks, checked after the last stage.
+///
+/// Columns of another dtype are cast when no value is lost (`"42"` to
+/// `Int64`, `Int32` to `Int64`, `2.0` to `Int64`); anything else is reported
+/// as a mismatch, together with every other mismatch, in one
+/// [`Error::Schema`].
+///
+/// ```ignore
+/// let contract = SchemaContract::new()
+///     .column("id", DataType::Int64, false)
+///     .column("title", DataType::String, true);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct SchemaContract {
+    columns: Vec<ColumnSpec>,
+    allow_extra_columns: bool,
+}
+
+/// A difference between a frame and its contract.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Mismatch {
+    Missing { column: String, expected: DataType },
+    Type { column: String, expected: DataType, found: DataType, reason: String },
+    Nulls { column: String, count: usize },
+    Extra { column: String, found: DataType },
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mismatch::Missing { column, expected } => {
+                write!(f, "- {column}: missing (expected {expected})")
+            }
+            Mismatch::Type { column, expected, found, reason } => {
+                write!(f, "~ {column}: expected {expected}, found {found} ({reason})")
+            }
+            Mismatch::Nulls { column, count } => {
+                write!(f, "! {column}: {count} nulls in a non-nullable column")
+            }
+            Mismatch::Extra { column, found } => write!(f, "+ {column}: unexpected column ({found})"),
+        }
+    }
+}
+
+impl SchemaContract {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn column(mut self, name: impl Into<String>, dtype: DataType, nullable: bool) -> Self {
+        self.columns.push(ColumnSpec {
+            name: name.into(),
+            dtype,
+            nullable,
+        });
+        self
+    }
+
+    /// Keep columns the contract does not mention instead of rejecting them.
+    pub fn allow_extra_columns(mut self, allow: bool) -> Self {
+        self.allow_extra_columns = allow;
+        self
+    }
+
+    pub fn columns(&self) -> &[ColumnSpec] {
+        &self.columns
+    }
+
+    /// Cast `df` to the contract. Contract columns come first, in contract
+    /// order, followed by any allowed extra columns.
+    pub fn enforce(&self, df: DataFrame) -> Result<DataFrame> {
+        let (df, mismatches) = self.conform(df)?;
+        if mismatches.is_empty() {
+            return Ok(df);
+        }
+        let diff: Vec<String> = mismatches.iter().map(ToString::to_string).collect();
+        Err(Error::Schema(format!(
+            "{} schema mismatches: {}",
+            mismatches.len(),
+            diff.join("; ")
+        )))
+    }
+
+    /// Cast what can be cast safely and list everything else.
+    pub fn conform(&self, df: DataFrame) -> Result<(DataFrame, Vec<Mismatch>)> {
+        let mut mismatches = Vec::new();
+        let mut columns = Vec::with_capacity(df.width());
+
+        for spec in &self.columns {
+            let Some(column) = df.column(&spec.name).ok() else {
+                mismatches.push(Mismatch::Missing {
+                    column: spec.name.clone(),
+                    expected: spec.dtype.clone(),
+                });
+                continue;
+            };
+
+            let column = if column.dtype() == &spec.dtype {
+                column.clone()
+            } else {
+                match safe_cast(column, &spec.dtype) {
+                    Ok(cast) => {
+                        tracing::info!("Cast {} from {} to {}", spec.name, column.dtype(), spec.dtype);
+                        cast
+                    }
+                    Err(reason) => {
+                        mismatches.push(Mismatch::Type {
+                            column: spec.name.clone(),
+                            expected: spec.dtype.clone(),
+                            found: column.dtype().clone(),
+                            reason,
+                        });
+                        column.clone()
+                    }
+                }
+            };
+
+            if !spec.nullable && column.null_count() > 0 {
+                mismatches.push(Mismatch::Nulls {
+                    column: spec.name.clone(),
+                    count: column.null_count(),
+                });
+            }
+            columns.push(column);
+        }
+
+        for column in df.get_columns() {
+            if self.columns.iter().any(|spec| spec.name == column.name().as_str()) {
+                continue;
+            }
+            if self.allow_extra_columns {
+                columns.push(column.clone());
+            } else {
+                mismatches.push(Mismatch::Extra {
+                    column: column.name().to_string(),
+                    found: column.dtype().clone(),
+                });
+            }
+        }
+
+        Ok((DataFrame::new(columns)?, mismatches))
+    }
+}
+
+/// Cast without losing values: nothing may turn null, and every value must
+/// survive the round trip back to its own type wherever that cast exists
+/// (no truncated floats or timestamps, no `2` read as `true`). Strings are
+/// parsed, not converted, so `"01"` may become `1` without the check.
+fn safe_cast(column: &Column, dtype: &DataType) -> std::result::Result<Column, String> {
+    let cast = column.cast(dtype).map_err(|e| e.to_string())?;
+
+    let lost = column.is_not_null() & cast.is_null();
+    if lost.any() {
+        return Err(format!(
+            "{} values cannot be cast, e.g. {}",
+            lost.sum().unwrap_or(0),
+            examples(column, &lost)
+        ));
+    }
+
+    if column.dtype() != &DataType::String
+        && let Ok(back) = cast.cast(column.dtype())
+    {
+        let changed = column
+            .as_materialized_series()
+            .not_equal_missing(back.as_materialized_series())
+            .map_err(|e| e.to_string())?;
+        if changed.any() {
+            return Err(format!(
+                "{} values would change, e.g. {}",
+                changed.sum().unwrap_or(0),
+                examples(column, &changed)
+            ));
+        }
+    }
+    Ok(cast)
+}
+
+/// The first few values selected by `mask`, for error messages.
+fn examples(column: &Column, mask: &BooleanChunked) -> String {
+    let Ok(values) = column.filter(mask).and_then(|c| c.cast(&DataType::String)) else {
+        return String::new();
+    };
+    let Ok(values) = values.str().cloned() else {
+        return String::new();
+    };
+    values
+        .into_iter()
+        .take(3)
+        .map(|v| format!("{:?}", v.unwrap_or_default()))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// ============================================================================
+// Config
+// ============================================================================
+
+/// A contract in a job file.
+///
+/// ```json
+/// {
+///   "columns": [
+///     { "name": "id", "dtype": "int64", "nullable": false },
+///     { "name": "created", "dtype": "datetime[ms]" }
+///   ],
+///   "allow_extra_columns": true
+/// }
+/// ```
+#[derive(Clone, Debug, Deserialize)]
+pub struct ContractConfig {
+    pub columns: Vec<ColumnConfig>,
+    #[serde(default)]
+    pub allow_extra_columns: bool,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ColumnConfig {
+    pub name: String,
+    pub dtype: String,
+    #[serde(default = "nullable_default")]
+    pub nullable: bool,
+}
+
+fn nullable_default() -> bool {
+    true
+}
+
+impl ContractConfig {
+    pub fn into_contract(self) -> Result<SchemaContract> {
+        let mut contract = SchemaContract::new().allow_extra_columns(self.allow_extra_columns);
+        for column in self.columns {
+            contract = contract.column(column.name, parse_dtype(&column.dtype)?, column.nullable);
+        }
+        Ok(contract)
+    }
+}
+
+/// Parse a dtype name such as `int64`, `str` or `datetime[us]`.
+pub fn parse_dtype(name: &str) -> Result<DataType> {
+    let name = name.trim().to_ascii_lowercase();
+    Ok(match name.as_str() {
+        "bool" | "boolean" => DataType::Boolean,
+        "int8" | "i8" => DataType::Int8,
+        "int16" | "i16" => DataType::Int16,
+        "int32" | "i32" => DataType::Int32,
+        "int64" | "i64" => DataType::Int64,
+        "uint8" | "u8" => DataType::UInt8,
+        "uint16" | "u16" => DataType::UInt16,
+        "uint32" | "u32" => DataType::UInt32,
+        "uint64" | "u64" => DataType::UInt64,
+        "float32" | "f32" => DataType::Float32,
+        "float64" | "f64" => DataType::Float64,
+        "string" | "str" | "utf8" => DataType::String,
+        "date" => DataType::Date,
+        "time" => DataType::Time,
+        "datetime" | "datetime[us]" => DataType::Datetime(TimeUnit::Microseconds, None),
+        "datetime[ms]" => DataType::Datetime(TimeUnit::Milliseconds, None),
+        "datetime[ns]" => DataType::Datetime(TimeUnit::Nanoseconds, None),
+        _ => return Err(Error::Config(format!("unknown dtype '{name}'"))),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cast(values: Series, dtype: DataType) -> std::result::Result<Column, String> {
+        safe_cast(&values.into_column(), &dtype)
+    }
+
+    fn timestamps(unit: TimeUnit, values: &[i64]) -> Series {
+        Series::new("ts".into(), values).cast(&DataType::Datetime(unit, None)).unwrap()
+    }
+
+    #[test]
+    fn safe_cast_accepts_lossless_casts() {
+        let ints = Series::new("n".into(), [Some(1i64), None, Some(3)]);
+        assert_eq!(cast(ints.clone(), DataType::Float64).unwrap().null_count(), 1);
+        assert!(cast(ints, DataType::String).is_ok());
+        assert!(cast(Series::new("n".into(), [0i64, 1]), DataType::Boolean).is_ok());
+        assert!(cast(Series::new("n".into(), [0.5f64, 1.25]), DataType::String).is_ok());
+        assert!(cast(timestamps(TimeUnit::Milliseconds, &[0, 86_400_000]), DataType::Date).is_ok());
+        assert!(cast(Series::new("s".into(), ["01", "2"]), DataType::Int64).is_ok());
+    }
+
+    #[test]
+    fn safe_cast_rejects_values_that_turn_null() {
+        let err = cast(Series::new("s".into(), ["1", "x"]), DataType::Int64).unwrap_err();
+        assert!(err.starts_with("1 values cannot be cast"), "{err}");
+    }
+
+    #[test]
+    fn safe_cast_rejects_lossy_round_trips() {
+        let lossy = [
+            (Series::new("n".into(), [1.5f64]), DataType::Int64),
+            (Series::new("n".into(), [2i64]), DataType::Boolean),
+            (timestamps(TimeUnit::Milliseconds, &[90_000_000]), DataType::Date),
+            (
+                timestamps(TimeUnit::Microseconds, &[1_500]),
+                DataType::Datetime(TimeUnit::Milliseconds, None),
+            ),
+        ];
+        for (values, dtype) in lossy {
+            let err = cast(values, dtype.clone()).unwrap_err();
+            assert!(err.contains("would change"), "{dtype}: {err}");
+        }
+    }
+}
@@ -1,34 +1,161 @@
 use std::{
     borrow::Cow,
-    fs::File,
-    sync::Arc,
-    time::{SystemTime, UNIX_EPOCH},
+    collections::{BTreeMap, HashSet},
+    path::{Path, PathBuf},
+    sync::{Arc, LazyLock},
+    time::Instant,
 };
 
 use async_trait::async_trait;
 use polars::prelude::*;
-use sqlx::{postgres::PgPoolCopyExt, Acquire, Pool, Postgres};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use sqlx::{Acquire, PgConnection, Pool, Postgres, Sqlite};
+use tokio::sync::mpsc::Receiver;
+use tracing::{info, info_span, warn, Instrument};
 
-use crate::errors::Result;
+use crate::errors::{Error, Result};
+use crate::context;
+use crate::files::{apply_retention, output_path, AtomicFile, FileOptions, OutputFile};
+use crate::formats::{CsvSinkOptions, JsonArrayWriter, ParquetSinkOptions};
+use crate::partitioning::{plan_partitions, write_partitions, write_success_marker, PartitionMode};
+use crate::metrics::metrics;
+use crate::sqlite::{polars_to_sqlite_dtype, save_data_to_sqlite, SqliteStatements, SqliteWriteMode};
 
 // ============================================================================
 // Trait: Sink
 // ============================================================================
 
+/// A sink shared between jobs and pipelines.
+pub type SharedSink<'a> = Arc<dyn Sink + Send + Sync + 'a>;
+
 /// Anything that can persist a `DataFrame`.
 #[async_trait]
 pub trait Sink {
     async fn save_data(&self, df: &mut DataFrame) -> Result<()>;
+
+    /// Short label used for metrics and logs.
+    fn kind(&self) -> &'static str {
+        "custom"
+    }
+
+    /// Describe what `save_data` would do with `df` without doing it, for
+    /// dry runs. Defaults to the row count only.
+    async fn plan(&self, df: &DataFrame) -> Result<SinkPlan> {
+        Ok(SinkPlan::new(self.kind(), "", df))
+    }
+
+    /// Write batches from `rx` as they arrive until the channel closes and
+    /// return the number of rows written.
+    ///
+    /// Defaults to one `save_data` per batch, which suits appending sinks.
+    async fn save_stream(&self, mut rx: Receiver<DataFrame>) -> Result<usize> {
+        let mut rows = 0;
+        while let Some(mut df) = rx.recv().await {
+            self.save_data(&mut df).await?;
+            rows += df.height();
+        }
+        Ok(rows)
+    }
+
+    /// `save_data`, also returning the row counts of sinks that upsert.
+    /// Defaults to `None`.
+    async fn save_data_with_counts(&self, df: &mut DataFrame) -> Result<Option<UpsertCounts>> {
+        self.save_data(df).await?;
+        Ok(None)
+    }
+
+    /// `save_stream`, also returning upsert row counts summed over batches.
+    /// Defaults to `None`.
+    async fn save_stream_with_counts(
+        &self,
+        rx: Receiver<DataFrame>,
+    ) -> Result<(usize, Option<UpsertCounts>)> {
+        Ok((self.save_stream(rx).await?, None))
+    }
+}
+
+/// What a sink would write during a dry run.
+#[derive(Clone, Debug, Serialize)]
+pub struct SinkPlan {
+    pub sink: String,
+    /// File path or `schema.table`.
+    pub target: String,
+    pub rows: usize,
+    /// Column names with the type they would be written as.
+    pub columns: Vec<(String, String)>,
+    /// Files that would be written.
+    pub files: Vec<String>,
+    /// SQL in execution order; COPY statements stream the rows as CSV.
+    pub statements: Vec<String>,
+}
+
+impl SinkPlan {
+    /// A plan with the frame's columns and Polars dtypes.
+    pub fn new(sink: &str, target: impl Into<String>, df: &DataFrame) -> Self {
+        Self {
+            sink: sink.to_string(),
+            target: target.into(),
+            rows: df.height(),
+            columns: df
+                .get_columns()
+                .iter()
+                .map(|c| (c.name().to_string(), c.dtype().to_string()))
+                .collect(),
+            files: Vec::new(),
+            statements: Vec::new(),
+        }
+    }
 }
 
 // ============================================================================
 // Enum: Sinker
 // ============================================================================
 
+/// File sinks write to a temporary sibling and rename it into place, so a
+/// failed run never leaves a truncated file at `path`. CSV in append mode is
+/// the exception: it writes to `path` directly.
+///
+/// Paths may be templates such as `out/{run_date}/pages_{run_id}.parquet`,
+/// rendered per run by [`RunContext::render`](crate::context::RunContext::render);
+/// missing parent directories are created.
 #[derive(Clone, Debug)]
 pub enum Sinker<'a> {
-    Csv(Cow<'a, str>),
-    Parquet(Cow<'a, str>),
+    Csv {
+        path: Cow<'a, str>,
+        options: CsvSinkOptions,
+        file: FileOptions,
+    },
+    Parquet {
+        path: Cow<'a, str>,
+        options: ParquetSinkOptions,
+        file: FileOptions,
+    },
+    /// One JSON array of row objects.
+    Json {
+        path: Cow<'a, str>,
+        file: FileOptions,
+    },
+    /// One JSON object per line.
+    NdJson {
+        path: Cow<'a, str>,
+        file: FileOptions,
+    },
+    /// Arrow IPC file (Feather v2).
+    Ipc {
+        path: Cow<'a, str>,
+        file: FileOptions,
+    },
+    /// Hive-style dataset: `root/col=value/part-<id>-00000.parquet`.
+    PartitionedParquet {
+        root: Cow<'a, str>,
+        partition_by: Vec<String>,
+        max_rows_per_file: Option<usize>,
+        mode: PartitionMode,
+        options: ParquetSinkOptions,
+        /// The marker goes in `root`; checksums are written per file.
+        file: FileOptions,
+    },
     Postgres {
         pool: Arc<Pool<Postgres>>,
         schema: Cow<'a, str>,
@@ -36,134 +163,1464 @@ pub enum Sinker<'a> {
         auto_create: bool,
         upsert: bool,
         primary_key: Option<Cow<'a, str>>,
+        /// Column types, plus the schema, indexes, comments and storage
+        /// options used by `auto_create`.
+        table_options: PostgresTableOptions,
+        /// Take an advisory lock on the table for each write, so concurrent
+        /// writers to it run one after another.
+        advisory_lock: bool,
+        /// Which conflicting rows an upsert rewrites.
+        change_detection: ChangeDetection,
+        /// Remove target rows whose keys are missing from the batch, for
+        /// sources that deliver full snapshots.
+        deletes: Option<DeletePropagation>,
+    },
+    Sqlite {
+        pool: Arc<Pool<Sqlite>>,
+        table: Cow<'a, str>,
+        auto_create: bool,
+        mode: SqliteWriteMode,
+        primary_key: Option<Cow<'a, str>>,
     },
 }
 
 impl<'a> Sinker<'a> {
     /// Create a CSV sinker.
     pub fn csv(path: impl Into<Cow<'a, str>>) -> Self {
-        Self::Csv(path.into())
+        Self::Csv {
+            path: path.into(),
+            options: CsvSinkOptions::default(),
+            file: FileOptions::default(),
+        }
+    }
+
+    /// Create a Parquet sinker.
+    pub fn parquet(path: impl Into<Cow<'a, str>>) -> Self {
+        Self::Parquet {
+            path: path.into(),
+            options: ParquetSinkOptions::default(),
+            file: FileOptions::default(),
+        }
+    }
+
+    /// Create a JSON array sinker. List and struct columns are written as
+    /// JSON arrays and objects.
+    pub fn json(path: impl Into<Cow<'a, str>>) -> Self {
+        Self::Json {
+            path: path.into(),
+            file: FileOptions::default(),
+        }
+    }
+
+    /// Create a newline-delimited JSON sinker.
+    pub fn ndjson(path: impl Into<Cow<'a, str>>) -> Self {
+        Self::NdJson {
+            path: path.into(),
+            file: FileOptions::default(),
+        }
+    }
+
+    /// Create an Arrow IPC sinker, readable as Feather by pyarrow.
+    pub fn ipc(path: impl Into<Cow<'a, str>>) -> Self {
+        Self::Ipc {
+            path: path.into(),
+            file: FileOptions::default(),
+        }
+    }
+
+    /// Create a Hive-partitioned Parquet sinker that appends by default.
+    pub fn partitioned_parquet<S: Into<String>>(
+        root: impl Into<Cow<'a, str>>,
+        partition_by: impl IntoIterator<Item = S>,
+    ) -> Self {
+        Self::PartitionedParquet {
+            root: root.into(),
+            partition_by: partition_by.into_iter().map(Into::into).collect(),
+            max_rows_per_file: None,
+            mode: PartitionMode::default(),
+            options: ParquetSinkOptions::default(),
+            file: FileOptions::default(),
+        }
+    }
+
+    /// Split partitions into files of at most `rows` rows.
+    pub fn with_max_rows_per_file(mut self, rows: usize) -> Self {
+        if let Sinker::PartitionedParquet { max_rows_per_file, .. } = &mut self {
+            *max_rows_per_file = Some(rows.max(1));
+        }
+        self
+    }
+
+    pub fn with_partition_mode(mut self, partition_mode: PartitionMode) -> Self {
+        if let Sinker::PartitionedParquet { mode, .. } = &mut self {
+            *mode = partition_mode;
+        }
+        self
+    }
+
+    /// Create a Postgres sinker.
+    pub fn postgres(
+        pool: Arc<Pool<Postgres>>,
+        schema: impl Into<Cow<'a, str>>,
+        table: impl Into<Cow<'a, str>>,
+        auto_create: bool,
+        upsert: bool,
+        primary_key:  Option<Cow<'a, str>>,
+    ) -> Self {
+        Self::Postgres {
+            pool,
+            schema: schema.into(),
+            table: table.into(),
+            auto_create,
+            upsert,
+            primary_key,
+            table_options: PostgresTableOptions::default(),
+            advisory_lock: false,
+            change_detection: ChangeDetection::default(),
+            deletes: None,
+        }
+    }
+
+    /// Append-only `<table>_rejected` table for quarantined rows, created on first use.
+    pub fn postgres_rejected(
+        pool: Arc<Pool<Postgres>>,
+        schema: impl Into<Cow<'a, str>>,
+        table: &str,
+    ) -> Self {
+        Self::postgres(pool, schema, format!("{table}_rejected"), true, false, None)
+    }
+
+    /// Create a SQLite sinker. Upserts need a primary key; see
+    /// [`with_primary_key`](Self::with_primary_key).
+    pub fn sqlite(
+        pool: Arc<Pool<Sqlite>>,
+        table: impl Into<Cow<'a, str>>,
+        auto_create: bool,
+        mode: SqliteWriteMode,
+    ) -> Self {
+        Self::Sqlite {
+            pool,
+            table: table.into(),
+            auto_create,
+            mode,
+            primary_key: None,
+        }
+    }
+
+    fn file_options_mut(&mut self) -> Option<&mut FileOptions> {
+        match self {
+            Sinker::Csv { file, .. }
+            | Sinker::Parquet { file, .. }
+            | Sinker::Json { file, .. }
+            | Sinker::NdJson { file, .. }
+            | Sinker::Ipc { file, .. }
+            | Sinker::PartitionedParquet { file, .. } => Some(file),
+            Sinker::Postgres { .. } | Sinker::Sqlite { .. } => None,
+        }
+    }
+
+    /// Marker and checksum settings for file sinks; ignored by databases.
+    pub fn with_file_options(mut self, options: FileOptions) -> Self {
+        if let Some(file) = self.file_options_mut() {
+            *file = options;
+        }
+        self
+    }
+
+    /// Touch `_SUCCESS` next to the file once it is in place.
+    pub fn with_success_marker(mut self) -> Self {
+        if let Some(file) = self.file_options_mut() {
+            file.success_marker = true;
+        }
+        self
+    }
+
+    /// Write a `<file>.sha256` sidecar once the file is in place.
+    pub fn with_checksum(mut self) -> Self {
+        if let Some(file) = self.file_options_mut() {
+            file.checksum = true;
+        }
+        self
+    }
+
+    /// Delimiter, formatting, append and compression settings for CSV sinks.
+    pub fn with_csv_options(mut self, csv: CsvSinkOptions) -> Self {
+        if let Sinker::Csv { options, .. } = &mut self {
+            *options = csv;
+        }
+        self
+    }
+
+    /// Compression, row group and metadata settings for Parquet sinks.
+    pub fn with_parquet_options(mut self, parquet: ParquetSinkOptions) -> Self {
+        if let Sinker::Parquet { options, .. } | Sinker::PartitionedParquet { options, .. } =
+            &mut self
+        {
+            *options = parquet;
+        }
+        self
+    }
+
+    /// Indexes, comments, `UNLOGGED` and range partitioning for tables that
+    /// `auto_create` creates.
+    pub fn with_table_options(mut self, options: PostgresTableOptions) -> Self {
+        if let Sinker::Postgres { table_options, .. } = &mut self {
+            *table_options = options;
+        }
+        self
+    }
+
+    /// Declare `column` as `pg_type` (e.g. `uuid`, `varchar(64)`,
+    /// `numeric(18,4)`) instead of the type mapped from its dtype.
+    pub fn with_column_type(mut self, column: impl Into<String>, pg_type: impl Into<String>) -> Self {
+        if let Sinker::Postgres { table_options, .. } = &mut self {
+            table_options.column_types.insert(column.into(), pg_type.into());
+        }
+        self
+    }
+
+    /// Serialize writes to the target table across jobs and processes with a
+    /// Postgres advisory lock held for the length of each write's transaction.
+    pub fn with_advisory_lock(mut self) -> Self {
+        if let Sinker::Postgres { advisory_lock, .. } = &mut self {
+            *advisory_lock = true;
+        }
+        self
+    }
+
+    /// Only rewrite rows whose values changed when upserting; see
+    /// [`ChangeDetection`].
+    pub fn with_change_detection(mut self, detection: ChangeDetection) -> Self {
+        if let Sinker::Postgres { change_detection, .. } = &mut self {
+            *change_detection = detection;
+        }
+        self
+    }
+
+    /// Treat each upserted batch as a full snapshot and delete, or mark as
+    /// deleted, target rows whose keys it lacks; see [`DeletePropagation`].
+    /// Streaming writes are rejected, as no single batch is the snapshot.
+    pub fn with_delete_propagation(mut self, propagation: DeletePropagation) -> Self {
+        if let Sinker::Postgres { deletes, .. } = &mut self {
+            *deletes = Some(propagation);
+        }
+        self
+    }
+
+    /// Optional helper to attach a primary key.
+    pub fn with_primary_key(mut self, pk: impl Into<Cow<'a, str>>) -> Self {
+        if let Sinker::Postgres { primary_key, .. } | Sinker::Sqlite { primary_key, .. } = &mut self
+        {
+            *primary_key = Some(pk.into());
+        }
+        self
+    }
+}
+
+// ============================================================================
+// Impl: Sink for Sinker
+// ============================================================================
+
+#[async_trait]
+impl<'a> Sink for Sinker<'a> {
+    fn kind(&self) -> &'static str {
+        match self {
+            Sinker::Csv { .. } => "csv",
+            Sinker::Parquet { .. } => "parquet",
+            Sinker::Json { .. } => "json",
+            Sinker::NdJson { .. } => "ndjson",
+            Sinker::Ipc { .. } => "ipc",
+            Sinker::PartitionedParquet { .. } => "partitioned_parquet",
+            Sinker::Postgres { .. } => "postgres",
+            Sinker::Sqlite { .. } => "sqlite",
+        }
+    }
+
+    async fn save_data(&self, df: &mut DataFrame) -> Result<()> {
+        self.save_data_with_counts(df).await.map(|_| ())
+    }
+
+    async fn save_data_with_counts(&self, df: &mut DataFrame) -> Result<Option<UpsertCounts>> {
+        let started = Instant::now();
+        let result = self.write(df).await;
+        let outcome = if result.is_ok() { "success" } else { "failure" };
+        metrics()
+            .sink_duration
+            .with_label_values(&[self.kind(), outcome])
+            .observe(started.elapsed().as_secs_f64());
+        result
+    }
+
+    async fn plan(&self, df: &DataFrame) -> Result<SinkPlan> {
+        Ok(match self {
+            Sinker::Csv { path, .. }
+            | Sinker::Parquet { path, .. }
+            | Sinker::Json { path, .. }
+            | Sinker::NdJson { path, .. }
+            | Sinker::Ipc { path, .. } => {
+                let path = context::expand(path)?;
+                let mut plan = SinkPlan::new(self.kind(), path.as_str(), df);
+                plan.files.push(path);
+                plan
+            }
+            Sinker::PartitionedParquet {
+                root,
+                partition_by,
+                max_rows_per_file,
+                ..
+            } => {
+                let root = context::expand(root)?;
+                let files = plan_partitions(df, Path::new(&root), partition_by, *max_rows_per_file)?;
+                let mut plan = SinkPlan::new(self.kind(), root.as_str(), df);
+                plan.files = files.iter().map(|f| f.path.display().to_string()).collect();
+                plan
+            }
+            Sinker::Postgres {
+                schema,
+                table,
+                table_options,
+                ..
+            } => {
+                let statements = self.postgres_statements(df)?;
+                let mut plan = SinkPlan::new(self.kind(), format!("{schema}.{table}"), df);
+                plan.columns = postgres_column_types(df, table_options)?;
+                plan.statements = statements.into_vec();
+                plan
+            }
+            Sinker::Sqlite {
+                table,
+                auto_create,
+                mode,
+                primary_key,
+                ..
+            } => {
+                let statements = SqliteStatements::build(
+                    df,
+                    table,
+                    *auto_create,
+                    *mode,
+                    primary_key.as_deref(),
+                )?;
+                let mut plan = SinkPlan::new(self.kind(), table.as_ref(), df);
+                plan.columns = df
+                    .get_columns()
+                    .iter()
+                    .map(|c| Ok((c.name().to_string(), polars_to_sqlite_dtype(c.dtype())?)))
+                    .collect::<Result<_>>()?;
+                plan.statements = statements.into_vec(df.width());
+                plan
+            }
+        })
+    }
+
+    async fn save_stream(&self, rx: Receiver<DataFrame>) -> Result<usize> {
+        Ok(self.save_stream_with_counts(rx).await?.0)
+    }
+
+    async fn save_stream_with_counts(
+        &self,
+        rx: Receiver<DataFrame>,
+    ) -> Result<(usize, Option<UpsertCounts>)> {
+        let started = Instant::now();
+        let result = self.write_stream(rx).await;
+        let outcome = if result.is_ok() { "success" } else { "failure" };
+        metrics()
+            .sink_duration
+            .with_label_values(&[self.kind(), outcome])
+            .observe(started.elapsed().as_secs_f64());
+        result
+    }
+}
+
+impl<'a> Sinker<'a> {
+    /// Files keep one writer open across batches (CSV writes its header once);
+    /// Postgres runs one COPY per batch and SQLite one transaction per batch;
+    /// SQLite's overwrite mode only clears the table before the first batch.
+    async fn write_stream(
+        &self,
+        mut rx: Receiver<DataFrame>,
+    ) -> Result<(usize, Option<UpsertCounts>)> {
+        let mut rows = 0;
+        let mut upserts: Option<UpsertCounts> = None;
+        match self {
+            Sinker::Csv {
+                path,
+                options,
+                file,
+            } => {
+                let mut out = OutputFile::open(output_path(path)?, options.append)?;
+                let mut header = options.header_for(out.is_new());
+                let mut encoder = options.encoder(out.file())?;
+                while let Some(mut df) = rx.recv().await {
+                    options.writer(&mut encoder, header)?.finish(&mut df)?;
+                    header = false;
+                    rows += df.height();
+                }
+                encoder.finish()?;
+                out.commit(*file)?;
+            }
+
+            Sinker::Parquet {
+                path,
+                options,
+                file,
+            } => {
+                // The schema comes from the first batch; no batches, no file.
+                let Some(first) = rx.recv().await else {
+                    return Ok((0, None));
+                };
+                let mut out = AtomicFile::create(output_path(path)?)?;
+                let mut writer = options.writer(out.file())?.batched(first.schema())?;
+                writer.write_batch(&first)?;
+                rows += first.height();
+                while let Some(df) = rx.recv().await {
+                    writer.write_batch(&df)?;
+                    rows += df.height();
+                }
+                writer.finish()?;
+                out.commit(*file)?;
+            }
+
+            Sinker::Json { path, file } => {
+                let mut out = AtomicFile::create(output_path(path)?)?;
+                let mut writer = JsonArrayWriter::new(out.file())?;
+                while let Some(mut df) = rx.recv().await {
+                    writer.write_batch(&mut df)?;
+                    rows += df.height();
+                }
+                writer.finish()?;
+                out.commit(*file)?;
+            }
+
+            Sinker::NdJson { path, file } => {
+                let mut out = AtomicFile::create(output_path(path)?)?;
+                while let Some(mut df) = rx.recv().await {
+                    JsonWriter::new(out.file())
+                        .with_json_format(JsonFormat::JsonLines)
+                        .finish(&mut df)?;
+                    rows += df.height();
+                }
+                out.commit(*file)?;
+            }
+
+            Sinker::Ipc { path, file } => {
+                // Like Parquet, the schema comes from the first batch.
+                let Some(first) = rx.recv().await else {
+                    return Ok((0, None));
+                };
+                let mut out = AtomicFile::create(output_path(path)?)?;
+                let mut writer = IpcWriter::new(out.file()).batched(first.schema())?;
+                writer.write_batch(&first)?;
+                rows += first.height();
+                while let Some(df) = rx.recv().await {
+                    writer.write_batch(&df)?;
+                    rows += df.height();
+                }
+                writer.finish()?;
+                out.commit(*file)?;
+            }
+
+            Sinker::PartitionedParquet {
+                root,
+                partition_by,
+                max_rows_per_file,
+                mode,
+                options,
+                file,
+            } => {
+                let root = &PathBuf::from(context::expand(root)?);
+                let mut cleaned = HashSet::new();
+                while let Some(df) = rx.recv().await {
+                    let files = plan_partitions(&df, root, partition_by, *max_rows_per_file)?;
+                    write_partitions(files, *mode, options, *file, &mut cleaned)?;
+                    rows += df.height();
+                }
+                if file.success_marker {
+                    write_success_marker(root)?;
+                }
+            }
+
+            Sinker::Postgres {
+                schema,
+                table,
+                deletes,
+                ..
+            } => {
+                // A batch is only part of the snapshot: propagating deletes
+                // per batch would remove every row the other batches hold.
+                if deletes.is_some() {
+                    return Err(Error::Config(format!(
+                        "delete propagation to {schema}.{table} needs the whole snapshot in one \
+                         write and can't be used with streaming"
+                    )));
+                }
+                while let Some(mut df) = rx.recv().await {
+                    if let Some(counts) = self.write(&mut df).await? {
+                        *upserts.get_or_insert_default() += counts;
+                    }
+                    rows += df.height();
+                }
+            }
+
+            Sinker::Sqlite {
+                pool,
+                table,
+                auto_create,
+                mode,
+                primary_key,
+            } => {
+                let mut mode = *mode;
+                while let Some(df) = rx.recv().await {
+                    save_data_to_sqlite(&df, pool, table, *auto_create, mode, primary_key.as_deref())
+                        .await?;
+                    if mode == SqliteWriteMode::Overwrite {
+                        mode = SqliteWriteMode::Append;
+                    }
+                    rows += df.height();
+                }
+            }
+        }
+        self.apply_retention();
+        Ok((rows, upserts))
+    }
+
+    /// Returns the upsert's row counts, or `None` if the sink didn't upsert.
+    async fn write(&self, df: &mut DataFrame) -> Result<Option<UpsertCounts>> {
+        let mut upserts = None;
+        match self {
+            Sinker::Csv {
+                path,
+                options,
+                file,
+            } => {
+                let mut out = OutputFile::open(output_path(path)?, options.append)?;
+                let header = options.header_for(out.is_new());
+                let mut encoder = options.encoder(out.file())?;
+                options.writer(&mut encoder, header)?.finish(df)?;
+                encoder.finish()?;
+                out.commit(*file)?;
+            }
+
+            Sinker::Parquet {
+                path,
+                options,
+                file,
+            } => {
+                let mut out = AtomicFile::create(output_path(path)?)?;
+                options.writer(out.file())?.finish(df)?;
+                out.commit(*file)?;
+            }
+
+            Sinker::Json { path, file } => {
+                let mut out = AtomicFile::create(output_path(path)?)?;
+                JsonWriter::new(out.file())
+                    .with_json_format(JsonFormat::Json)
+                    .finish(df)?;
+                out.commit(*file)?;
+            }
+
+            Sinker::NdJson { path, file } => {
+                let mut out = AtomicFile::create(output_path(path)?)?;
+                JsonWriter::new(out.file())
+                    .with_json_format(JsonFormat::JsonLines)
+                    .finish(df)?;
+                out.commit(*file)?;
+            }
+
+            Sinker::Ipc { path, file } => {
+                let mut out = AtomicFile::create(output_path(path)?)?;
+                IpcWriter::new(out.file()).finish(df)?;
+                out.commit(*file)?;
+            }
+
+            Sinker::PartitionedParquet {
+                root,
+                partition_by,
+                max_rows_per_file,
+                mode,
+                options,
+                file,
+            } => {
+                let root = &PathBuf::from(context::expand(root)?);
+                let files = plan_partitions(df, root, partition_by, *max_rows_per_file)?;
+                write_partitions(files, *mode, options, *file, &mut HashSet::new())?;
+                if file.success_marker {
+                    write_success_marker(root)?;
+                }
+            }
+
+            Sinker::Postgres {
+                pool,
+                schema,
+                table,
+                ..
+            } => {
+                let statements = self.postgres_statements(df)?;
+                upserts = save_data_to_postgres(df, pool, &statements).await?;
+                if let Some(counts) = &upserts {
+                    counts.record(&format!("{schema}.{table}"));
+                }
+            }
+
+            Sinker::Sqlite {
+                pool,
+                table,
+                auto_create,
+                mode,
+                primary_key,
+            } => {
+                save_data_to_sqlite(df, pool, table, *auto_create, *mode, primary_key.as_deref())
+                    .await?;
+            }
+        }
+        self.apply_retention();
+        Ok(upserts)
+    }
+
+    fn postgres_statements(&self, df: &DataFrame) -> Result<PostgresStatements> {
+        let Sinker::Postgres {
+            schema,
+            table,
+            auto_create,
+            upsert,
+            primary_key,
+            table_options,
+            advisory_lock,
+            change_detection,
+            deletes,
+            ..
+        } = self
+        else {
+            return Err(Error::Config(format!("{} is not a Postgres sink", self.kind())));
+        };
+        if deletes.is_some() && !upsert {
+            return Err(Error::Config(format!(
+                "delete propagation to {schema}.{table} needs upsert with a primary key"
+            )));
+        }
+        let upsert = upsert.then_some(UpsertOptions {
+            changes: change_detection,
+            deletes: deletes.as_ref(),
+        });
+        let statements = PostgresStatements::build(
+            df,
+            schema,
+            table,
+            *auto_create,
+            table_options,
+            upsert,
+            primary_key.as_deref(),
+        )?;
+        Ok(if *advisory_lock {
+            statements.with_advisory_lock(schema, table)
+        } else {
+            statements
+        })
+    }
+
+    /// Expire earlier outputs of a templated path once this write succeeded.
+    /// The write already stands, so a failure is logged rather than returned.
+    fn apply_retention(&self) {
+        let (template, file, nested) = match self {
+            Sinker::Csv { path, file, .. }
+            | Sinker::Parquet { path, file, .. }
+            | Sinker::Json { path, file }
+            | Sinker::NdJson { path, file }
+            | Sinker::Ipc { path, file } => (path, file, false),
+            Sinker::PartitionedParquet { root, file, .. } => (root, file, true),
+            Sinker::Postgres { .. } | Sinker::Sqlite { .. } => return,
+        };
+        if let Some(days) = file.retention_days
+            && let Err(e) = apply_retention(template, days, nested)
+        {
+            warn!("retention for {} failed: {}", template, e);
+        }
+    }
+}
+
+// ============================================================================
+// Postgres Helpers
+// ============================================================================
+
+/// Double-quote an identifier and escape inner quotes.
+fn q(id: &str) -> String {
+    format!("\"{}\"", id.replace('"', "\"\""))
+}
+
+/// Which rows an upsert rewrites when their key already exists.
+///
+/// Skipping unchanged rows avoids WAL, index churn and `updated_at` trigger
+/// firings for rows that are identical to the target. Comparing `json`
+/// columns is not supported by Postgres; use `jsonb` or a hash column.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeDetection {
+    /// Update every conflicting row.
+    #[default]
+    Always,
+    /// Update rows whose non-key columns differ from the target.
+    Columns,
+    /// Update rows whose value in this column, e.g. a row hash computed
+    /// upstream, differs from the target.
+    Hash(String),
+}
+
+/// How target rows missing from a full-snapshot batch are removed.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeleteMode {
+    /// `DELETE` the rows.
+    Hard,
+    /// Set this `timestamptz` column, e.g. `deleted_at`, to `now()`. Rows
+    /// that reappear in a later snapshot get it cleared again.
+    Soft(String),
+}
+
+/// Propagate upstream deletions on upsert: target rows whose key is not in
+/// the batch are deleted in the same transaction.
+///
+/// ```json
+/// { "mode": { "soft": "deleted_at" }, "max_deleted_percent": 5.0 }
+/// ```
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct DeletePropagation {
+    pub mode: DeleteMode,
+    /// Abort the write, rolling back the upsert, when more than this share of
+    /// the target's live rows would be deleted; guards against a truncated
+    /// or empty snapshot wiping the table.
+    #[serde(default = "DeletePropagation::default_max_deleted_percent")]
+    pub max_deleted_percent: f64,
+}
+
+impl DeletePropagation {
+    fn default_max_deleted_percent() -> f64 {
+        10.0
+    }
+
+    pub fn hard() -> Self {
+        Self {
+            mode: DeleteMode::Hard,
+            max_deleted_percent: Self::default_max_deleted_percent(),
+        }
+    }
+
+    pub fn soft(column: impl Into<String>) -> Self {
+        Self {
+            mode: DeleteMode::Soft(column.into()),
+            max_deleted_percent: Self::default_max_deleted_percent(),
+        }
+    }
+
+    pub fn max_deleted_percent(mut self, percent: f64) -> Self {
+        self.max_deleted_percent = percent;
+        self
+    }
+
+    fn soft_column(&self) -> Option<&str> {
+        match &self.mode {
+            DeleteMode::Hard => None,
+            DeleteMode::Soft(column) => Some(column),
+        }
+    }
+}
+
+/// Rows affected by one upsert.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct UpsertCounts {
+    pub inserted: u64,
+    pub updated: u64,
+    /// Rows whose key existed and were left as they were.
+    pub unchanged: u64,
+    /// Target rows deleted or marked deleted by delete propagation.
+    pub deleted: u64,
+}
+
+impl std::ops::AddAssign for UpsertCounts {
+    fn add_assign(&mut self, other: Self) {
+        self.inserted += other.inserted;
+        self.updated += other.updated;
+        self.unchanged += other.unchanged;
+        self.deleted += other.deleted;
+    }
+}
+
+impl UpsertCounts {
+    /// Log the counts and add them to the `sink_upsert_rows_total` metric.
+    fn record(&self, table: &str) {
+        info!(
+            table,
+            inserted = self.inserted,
+            updated = self.updated,
+            unchanged = self.unchanged,
+            deleted = self.deleted,
+            "upserted rows"
+        );
+        for (outcome, rows) in [
+            ("inserted", self.inserted),
+            ("updated", self.updated),
+            ("unchanged", self.unchanged),
+            ("deleted", self.deleted),
+        ] {
+            metrics().upsert_rows.with_label_values(&[table, outcome]).inc_by(rows);
+        }
+    }
+}
+
+/// Index access methods for [`PostgresIndex`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IndexMethod {
+    #[default]
+    Btree,
+    /// For `jsonb` columns (containment and key lookups) and full-text search.
+    Gin,
+    /// Small indexes over naturally ordered columns such as load timestamps.
+    Brin,
+}
+
+impl IndexMethod {
+    fn as_sql(self) -> &'static str {
+        match self {
+            IndexMethod::Btree => "btree",
+            IndexMethod::Gin => "gin",
+            IndexMethod::Brin => "brin",
+        }
+    }
+}
+
+/// A secondary index created with the table.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct PostgresIndex {
+    pub columns: Vec<String>,
+    pub method: IndexMethod,
+    pub unique: bool,
+    /// Defaults to `<table>_<columns>_idx`.
+    pub name: Option<String>,
+}
+
+impl PostgresIndex {
+    pub fn new<S: Into<String>>(columns: impl IntoIterator<Item = S>) -> Self {
+        Self {
+            columns: columns.into_iter().map(Into::into).collect(),
+            ..Self::default()
+        }
+    }
+
+    pub fn method(mut self, method: IndexMethod) -> Self {
+        self.method = method;
+        self
+    }
+
+    pub fn unique(mut self) -> Self {
+        self.unique = true;
+        self
+    }
+
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    fn name_for(&self, table: &str) -> String {
+        match &self.name {
+            Some(name) => name.clone(),
+            None => truncate_identifier(format!("{table}_{}_idx", self.columns.join("_"))),
+        }
+    }
+}
+
+/// Column types for Postgres writes, and how `auto_create` creates a table
+/// beyond its columns. Writes create whatever is missing:
+///
+/// ```json
+/// { "column_types": { "id": "uuid", "amount": "numeric(18,4)" },
+///   "indexes": [{ "columns": ["body"], "method": "gin" }],
+///   "comments": { "id": "Confluence content id" },
+///   "partition_by_range": "created_at" }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct PostgresTableOptions {
+    /// Column name to Postgres type, overriding the mapped type. COPY sends
+    /// every value as text and Postgres converts it with the type's input
+    /// function, as a `::type` cast would, so string columns load into
+    /// `uuid`, `citext` or `numeric(18,4)` columns as long as they parse.
+    ///
+    /// Types must be plain type names: a (possibly schema-qualified or
+    /// quoted) name with optional modifiers and array brackets, e.g.
+    /// `timestamp(3) with time zone` or `text[]`; constraints and defaults
+    /// are refused.
+    pub column_types: BTreeMap<String, String>,
+    /// Fail on columns without an explicit mapping (e.g. `i128`)
+    /// instead of creating them as `text`, unless `column_types` covers them.
+    pub strict_types: bool,
+    pub indexes: Vec<PostgresIndex>,
+    /// Column name to comment.
+    pub comments: BTreeMap<String, String>,
+    /// Skip the WAL: faster writes, but the table is emptied after a crash
+    /// and not replicated. Suits staging tables.
+    pub unlogged: bool,
+    /// Partition by range of this column. A `<table>_default` partition is
+    /// created so rows always have somewhere to go; attach range partitions
+    /// as needed. The primary key must include this column.
+    pub partition_by_range: Option<String>,
+}
+
+impl PostgresTableOptions {
+    pub fn column_type(mut self, column: impl Into<String>, pg_type: impl Into<String>) -> Self {
+        self.column_types.insert(column.into(), pg_type.into());
+        self
+    }
+
+    pub fn strict_types(mut self, strict: bool) -> Self {
+        self.strict_types = strict;
+        self
+    }
+
+    pub fn index(mut self, index: PostgresIndex) -> Self {
+        self.indexes.push(index);
+        self
+    }
+
+    pub fn comment(mut self, column: impl Into<String>, comment: impl Into<String>) -> Self {
+        self.comments.insert(column.into(), comment.into());
+        self
+    }
+
+    pub fn unlogged(mut self, unlogged: bool) -> Self {
+        self.unlogged = unlogged;
+        self
+    }
+
+    pub fn partition_by_range(mut self, column: impl Into<String>) -> Self {
+        self.partition_by_range = Some(column.into());
+        self
+    }
+
+    /// Reject options Postgres would only reject halfway through the DDL.
+    fn validate(&self, df: &DataFrame, primary_key: Option<&str>) -> Result<()> {
+        let has = |column: &str| df.get_column_index(column).is_some();
+        let named = self
+            .indexes
+            .iter()
+            .flat_map(|i| &i.columns)
+            .chain(self.comments.keys())
+            .chain(&self.partition_by_range);
+        for column in named {
+            if !has(column) {
+                return Err(Error::Config(format!("table option names unknown column '{column}'")));
+            }
+        }
+        if let Some(index) = self.indexes.iter().find(|i| i.columns.is_empty()) {
+            return Err(Error::Config(format!(
+                "index {:?} has no columns",
+                index.name.as_deref().unwrap_or_default()
+            )));
+        }
+        if let Some(range) = &self.partition_by_range {
+            if self.unlogged {
+                return Err(Error::Config("partitioned tables cannot be UNLOGGED".to_string()));
+            }
+            if primary_key.is_some_and(|pk| pk != range) {
+                return Err(Error::Config(format!(
+                    "primary key of a table partitioned by '{range}' must include '{range}'"
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Postgres truncates identifiers to 63 bytes; do it up front so the
+/// `IF NOT EXISTS` check sees the same name every run.
+fn truncate_identifier(mut name: String) -> String {
+    let mut end = name.len().min(63);
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+    name.truncate(end);
+    name
+}
+
+/// Single-quote a string literal.
+fn literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+/// `CREATE TABLE IF NOT EXISTS` for the frame's columns, with mapped types.
+pub fn create_table_sql(
+    df: &DataFrame,
+    schema: &str,
+    table: &str,
+    primary_key: Option<&str>,
+    options: &PostgresTableOptions,
+) -> Result<String> {
+    // Build `"col" TYPE` items
+    let cols: Vec<String> = postgres_column_types(df, options)?
+        .into_iter()
+        .map(|(name, pg)| format!("{} {}", q(&name), pg))
+        .collect();
+
+    let pk_clause = primary_key
+        .map(|pk| format!(", PRIMARY KEY ({})", q(pk)))
+        .unwrap_or_default();
+    let partition = options
+        .partition_by_range
+        .as_deref()
+        .map(|column| format!(" PARTITION BY RANGE ({})", q(column)))
+        .unwrap_or_default();
+
+    Ok(format!(
+        "CREATE {unlogged}TABLE IF NOT EXISTS {}.{} ({cols}{pk}){partition}",
+        q(schema),
+        q(table),
+        unlogged = if options.unlogged { "UNLOGGED " } else { "" },
+        cols = cols.join(", "),
+        pk = pk_clause
+    ))
+}
+
+/// Everything `auto_create` runs, in order: the schema, the table, its
+/// default partition, indexes and comments. Each statement is a no-op when
+/// its object already exists.
+pub fn create_table_statements(
+    df: &DataFrame,
+    schema: &str,
+    table: &str,
+    primary_key: Option<&str>,
+    options: &PostgresTableOptions,
+) -> Result<Vec<String>> {
+    Ok(TableDdl::build(df, schema, table, primary_key, options)?.into_vec())
+}
+
+/// Create the schema and table in Postgres if they don't already exist, in
+/// one transaction.
+pub async fn create_table_if_not_exists(
+    df: &DataFrame,
+    pool: &Pool<Postgres>,
+    schema: &str,
+    table: &str,
+    primary_key: Option<&str>,
+    options: &PostgresTableOptions,
+) -> Result<()> {
+    TableDdl::build(df, schema, table, primary_key, options)?
+        .apply(pool, None)
+        .await
+}
+
+/// [`create_table_statements`] grouped by the object each creates, so a
+/// write only runs the DDL for objects that are missing: `CREATE SCHEMA`
+/// needs the database's CREATE privilege and `CREATE INDEX` or `COMMENT ON`
+/// ownership of the table, even when `IF NOT EXISTS` makes them no-ops.
+struct TableDdl {
+    /// Quoted `schema` and `schema.table`, as `to_regnamespace` and
+    /// `to_regclass` parse them.
+    schema: String,
+    target: String,
+    create_schema: String,
+    /// The table and its default partition.
+    create_table: Vec<String>,
+    /// Quoted `schema.index` and its `CREATE INDEX`.
+    indexes: Vec<(String, String)>,
+    comments: Vec<String>,
+    /// Columns added to an existing table when missing, and their
+    /// `ALTER TABLE`.
+    columns: Vec<(String, String)>,
+}
+
+impl TableDdl {
+    fn build(
+        df: &DataFrame,
+        schema: &str,
+        table: &str,
+        primary_key: Option<&str>,
+        options: &PostgresTableOptions,
+    ) -> Result<Self> {
+        options.validate(df, primary_key)?;
+
+        let target = format!("{}.{}", q(schema), q(table));
+        let mut create_table = vec![create_table_sql(df, schema, table, primary_key, options)?];
+        if options.partition_by_range.is_some() {
+            let default = truncate_identifier(format!("{table}_default"));
+            create_table.push(format!(
+                "CREATE TABLE IF NOT EXISTS {}.{} PARTITION OF {target} DEFAULT",
+                q(schema),
+                q(&default)
+            ));
+        }
+        let indexes = options
+            .indexes
+            .iter()
+            .map(|index| {
+                let name = q(&index.name_for(table));
+                let columns = index.columns.iter().map(|c| q(c)).collect::<Vec<_>>();
+                let sql = format!(
+                    "CREATE {unique}INDEX IF NOT EXISTS {name} ON {target} USING {method} ({columns})",
+                    unique = if index.unique { "UNIQUE " } else { "" },
+                    method = index.method.as_sql(),
+                    columns = columns.join(", "),
+                );
+                (format!("{}.{name}", q(schema)), sql)
+            })
+            .collect();
+        let comments = options
+            .comments
+            .iter()
+            .map(|(column, comment)| {
+                format!("COMMENT ON COLUMN {target}.{} IS {}", q(column), literal(comment))
+            })
+            .collect();
+        Ok(Self {
+            schema: q(schema),
+            create_schema: format!("CREATE SCHEMA IF NOT EXISTS {}", q(schema)),
+            target,
+            create_table,
+            indexes,
+            comments,
+            columns: Vec::new(),
+        })
+    }
+
+    fn into_vec(self) -> Vec<String> {
+        std::iter::once(self.create_schema)
+            .chain(self.create_table)
+            .chain(self.indexes.into_iter().map(|(_, sql)| sql))
+            .chain(self.comments)
+            .chain(self.columns.into_iter().map(|(_, sql)| sql))
+            .collect()
+    }
+
+    /// Run the DDL for whatever is missing in one short transaction, after
+    /// `lock` (if any) so concurrent first writes don't race on
+    /// `IF NOT EXISTS`. The schema, table and comments are only created with
+    /// the table; indexes and columns are added to an existing table when
+    /// missing.
+    async fn apply(&self, pool: &Pool<Postgres>, lock: Option<&str>) -> Result<()> {
+        let exists = regclass_exists(pool, &self.target).await?;
+        let mut indexes = Vec::new();
+        for (name, sql) in &self.indexes {
+            if !exists || !regclass_exists(pool, name).await? {
+                indexes.push(sql);
+            }
+        }
+        let mut columns = Vec::new();
+        for (column, sql) in &self.columns {
+            if !exists || !self.column_exists(pool, column).await? {
+                columns.push(sql);
+            }
+        }
+        if exists && indexes.is_empty() && columns.is_empty() {
+            return Ok(());
+        }
+
+        let mut tx = pool.begin().await?;
+        if let Some(lock) = lock {
+            sqlx::query(lock).execute(&mut *tx).await?;
+        }
+        if !exists {
+            let schema_exists: bool = sqlx::query_scalar("SELECT to_regnamespace($1) IS NOT NULL")
+                .bind(&self.schema)
+                .fetch_one(&mut *tx)
+                .await?;
+            if !schema_exists {
+                sqlx::query(&self.create_schema).execute(&mut *tx).await?;
+            }
+            for sql in &self.create_table {
+                sqlx::query(sql).execute(&mut *tx).await?;
+            }
+        }
+        for sql in indexes {
+            sqlx::query(sql).execute(&mut *tx).await?;
+        }
+        if !exists {
+            for sql in &self.comments {
+                sqlx::query(sql).execute(&mut *tx).await?;
+            }
+        }
+        for sql in columns {
+            sqlx::query(sql).execute(&mut *tx).await?;
+        }
+        tx.commit().await?;
+        Ok(())
     }
 
-    /// Create a Parquet sinker.
-    pub fn parquet(path: impl Into<Cow<'a, str>>) -> Self {
-        Self::Parquet(path.into())
+    async fn column_exists(&self, pool: &Pool<Postgres>, column: &str) -> Result<bool> {
+        Ok(sqlx::query_scalar(
+            "SELECT EXISTS (SELECT 1 FROM pg_attribute
+             WHERE attrelid = to_regclass($1) AND attname = $2 AND NOT attisdropped)",
+        )
+        .bind(&self.target)
+        .bind(column)
+        .fetch_one(pool)
+        .await?)
     }
+}
 
-    /// Create a Postgres sinker.
-    pub fn postgres(
-        pool: Arc<Pool<Postgres>>,
-        schema: impl Into<Cow<'a, str>>,
-        table: impl Into<Cow<'a, str>>,
-        auto_create: bool,
-        upsert: bool,
-        primary_key:  Option<Cow<'a, str>>,
-    ) -> Self {
-        Self::Postgres {
-            pool,
-            schema: schema.into(),
-            table: table.into(),
-            auto_create,
-            upsert,
-            primary_key: primary_key,
-        }
-    }
+/// Whether `name` (quoted, optionally schema-qualified) names a table,
+/// index or other relation.
+async fn regclass_exists(pool: &Pool<Postgres>, name: &str) -> Result<bool> {
+    Ok(sqlx::query_scalar("SELECT to_regclass($1) IS NOT NULL")
+        .bind(name)
+        .fetch_one(pool)
+        .await?)
+}
 
-    /// Optional helper to attach a primary key.
-    pub fn with_primary_key(mut self, pk: impl Into<Cow<'a, str>>) -> Self {
-        if let Sinker::Postgres { primary_key, .. } = &mut self {
-            *primary_key = Some(pk.into());
+/// The statements one Postgres write runs, in order. COPY statements stream
+/// the frame as CSV.
+struct PostgresStatements {
+    /// DDL, committed on its own before the data transaction starts.
+    create_table: Option<TableDdl>,
+    lock: Option<String>,
+    create_stage: Option<String>,
+    copy: String,
+    upsert: Option<String>,
+    /// Upsert key that rows are deduplicated on before the COPY.
+    key: Option<String>,
+    deletes: Option<DeleteStatements>,
+}
+
+/// Upsert settings for [`PostgresStatements::build`].
+struct UpsertOptions<'o> {
+    changes: &'o ChangeDetection,
+    deletes: Option<&'o DeletePropagation>,
+}
+
+/// Delete propagation for one upsert: `count` returns the missing and the
+/// live target rows, checked against `max_deleted_percent` before `apply`.
+struct DeleteStatements {
+    count: String,
+    apply: String,
+    max_deleted_percent: f64,
+}
+
+impl DeleteStatements {
+    /// Refuse a snapshot that would remove more of the `live` rows than
+    /// allowed, e.g. because an upstream export came back truncated.
+    fn check(&self, missing: i64, live: i64) -> Result<()> {
+        if missing as f64 > live as f64 * self.max_deleted_percent / 100.0 {
+            return Err(Error::Quality(format!(
+                "snapshot would delete {missing} of {live} rows, more than the {}% allowed",
+                self.max_deleted_percent
+            )));
         }
-        self
+        Ok(())
     }
 }
 
-// ============================================================================
-// Impl: Sink for Sinker
-// ============================================================================
-
-#[async_trait]
-impl<'a> Sink for Sinker<'a> {
-    async fn save_data(&self, df: &mut DataFrame) -> Result<()> {
-        match self {
-            Sinker::Csv(path) => {
-                let mut file = File::create(path.as_ref())?;
-                CsvWriter::new(&mut file)
-                    .include_header(true)
-                    .with_quote_style(QuoteStyle::Necessary)
-                    .finish(df)?;
+impl PostgresStatements {
+    fn build(
+        df: &DataFrame,
+        schema: &str,
+        table: &str,
+        auto_create: bool,
+        options: &PostgresTableOptions,
+        upsert: Option<UpsertOptions<'_>>,
+        primary_key: Option<&str>,
+    ) -> Result<Self> {
+        let create_table = if auto_create {
+            let mut ddl = TableDdl::build(df, schema, table, primary_key, options)?;
+            if let Some(column) = upsert.as_ref().and_then(|u| u.deletes?.soft_column()) {
+                let sql = format!(
+                    "ALTER TABLE {}.{} ADD COLUMN IF NOT EXISTS {} timestamptz",
+                    q(schema),
+                    q(table),
+                    q(column)
+                );
+                ddl.columns.push((column.to_string(), sql));
             }
+            Some(ddl)
+        } else {
+            // Still map every type so unsupported columns fail the same way.
+            postgres_column_types(df, options)?;
+            None
+        };
 
-            Sinker::Parquet(path) => {
-                let mut file = File::create(path.as_ref())?;
-                ParquetWriter::new(&mut file).finish(df)?;
-            }
+        // Collect column names once, in frame order
+        let cols_df: Vec<String> = df
+            .get_column_names_owned()
+            .iter()
+            .map(|c| c.to_string())
+            .collect();
+        let cols_quoted: Vec<String> = cols_df.iter().map(|c| q(c)).collect();
 
-            Sinker::Postgres {
-                pool,
-                schema,
-                table,
-                auto_create,
-                upsert,
-                primary_key,
-            } => {
-                save_data_to_postgres(
-                    df,
-                    &**pool, // Arc<Pool<_>> -> &Pool<_>
-                    schema.as_ref(),
-                    table.as_ref(),
-                    *auto_create,
-                    *upsert,
-                    primary_key.as_deref(),
+        let Some(UpsertOptions { changes, deletes }) = upsert else {
+            let copy = format!(
+                "COPY {schema}.{table} ({cols}) FROM STDIN WITH (FORMAT csv)",
+                schema = q(schema),
+                table = q(table),
+                cols = cols_quoted.join(", "),
+            );
+            return Ok(Self {
+                lock: None,
+                create_table,
+                create_stage: None,
+                copy,
+                upsert: None,
+                key: None,
+                deletes: None,
+            });
+        };
+
+        let pk = match primary_key {
+            Some(pk) => pk,
+            None => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "upsert requested but no primary key was provided",
                 )
-                .await?;
+                .into());
+            }
+        };
+
+        // Unique per write, so concurrent upserts never share a stage name;
+        // the table part is shortened to stay within 63 bytes.
+        let prefix = truncate_identifier(table.replace('.', "_"));
+        let prefix = &prefix[..prefix.floor_char_boundary(24)];
+        let stage = format!("stage_{prefix}_{}", uuid::Uuid::new_v4().simple());
+
+        // Create TEMP stage with same structure. The stage has the target's
+        // column types, so COPY already parses each value with the target
+        // type's input function and the INSERT … SELECT below needs no casts.
+        let create_stage = format!(
+            "CREATE TEMP TABLE {stage} (LIKE {schema}.{table} INCLUDING ALL) ON COMMIT DROP",
+            stage = q(&stage),
+            schema = q(schema),
+            table = q(table),
+        );
+
+        let copy = format!(
+            "COPY {stage} ({cols}) FROM STDIN WITH (FORMAT csv)",
+            stage = q(&stage),
+            cols = cols_quoted.join(", "),
+        );
+
+        // Build UPDATE clause for non-PK columns
+        let non_pk: Vec<&String> = cols_df.iter().filter(|c| c.as_str() != pk).collect();
+        let mut non_pk_sets = non_pk
+            .iter()
+            .map(|c| format!("{} = EXCLUDED.{}", q(c), q(c)))
+            .collect::<Vec<_>>();
+        let mut changed = match changes {
+            ChangeDetection::Always => None,
+            ChangeDetection::Columns => {
+                let row = |prefix: &str| {
+                    let cols: Vec<String> =
+                        non_pk.iter().map(|c| format!("{prefix}.{}", q(c))).collect();
+                    format!("ROW({})", cols.join(", "))
+                };
+                Some(format!("{} IS DISTINCT FROM {}", row("target"), row("EXCLUDED")))
             }
+            ChangeDetection::Hash(column) => {
+                if !non_pk.contains(&column) {
+                    return Err(Error::Config(format!(
+                        "change detection column '{column}' is not a non-key column of the frame"
+                    )));
+                }
+                Some(format!("target.{c} IS DISTINCT FROM EXCLUDED.{c}", c = q(column)))
+            }
+        };
+        // Rows that come back after a soft delete are revived.
+        let soft_column = deletes.and_then(DeletePropagation::soft_column);
+        if let Some(column) = soft_column.filter(|c| !cols_df.iter().any(|d| d == c)) {
+            let deleted = format!("target.{} IS NOT NULL", q(column));
+            changed = match (non_pk_sets.is_empty(), changed) {
+                (true, _) => Some(deleted),
+                (false, None) => None,
+                (false, Some(changed)) => Some(format!("({changed}) OR {deleted}")),
+            };
+            non_pk_sets.push(format!("{} = NULL", q(column)));
         }
-        Ok(())
-    }
-}
+        let action = match (non_pk_sets.is_empty(), changed) {
+            (true, _) => "DO NOTHING".to_string(),
+            (false, None) => format!("DO UPDATE SET {}", non_pk_sets.join(", ")),
+            (false, Some(changed)) => {
+                format!("DO UPDATE SET {} WHERE {changed}", non_pk_sets.join(", "))
+            }
+        };
 
-// ============================================================================
-// Postgres Helpers
-// ============================================================================
+        // Inserting in key order makes concurrent upserts lock rows in the
+        // same order, so they wait on each other instead of deadlocking.
+        // `xmax = 0` holds for freshly inserted rows; rows an update skipped
+        // are not returned, which leaves them to be counted as unchanged.
+        let insert_sql = format!(
+            "WITH upserted AS (
+             INSERT INTO {schema}.{table} AS target ({cols})
+             SELECT {cols} FROM {stage} ORDER BY {pk}
+             ON CONFLICT ({pk}) {action}
+             RETURNING (xmax = 0) AS inserted)
+             SELECT count(*) FILTER (WHERE inserted), count(*) FILTER (WHERE NOT inserted) FROM upserted",
+            schema = q(schema),
+            table = q(table),
+            cols = cols_quoted.join(", "),
+            stage = q(&stage),
+            pk = q(pk),
+        );
 
-/// Double-quote an identifier and escape inner quotes.
-fn q(id: &str) -> String {
-    format!("\"{}\"", id.replace('"', "\"\""))
-}
+        let deletes = deletes.map(|propagation| {
+            let target = format!("{}.{}", q(schema), q(table));
+            let missing = format!(
+                "NOT EXISTS (SELECT 1 FROM {stage} AS staged WHERE staged.{pk} = target.{pk})",
+                stage = q(&stage),
+                pk = q(pk),
+            );
+            let (live, apply) = match soft_column {
+                None => (
+                    "TRUE".to_string(),
+                    format!("DELETE FROM {target} AS target WHERE {missing}"),
+                ),
+                Some(column) => {
+                    let live = format!("target.{} IS NULL", q(column));
+                    let apply = format!(
+                        "UPDATE {target} AS target SET {} = now() WHERE {live} AND {missing}",
+                        q(column)
+                    );
+                    (live, apply)
+                }
+            };
+            DeleteStatements {
+                count: format!(
+                    "SELECT count(*) FILTER (WHERE {missing}), count(*)
+                     FROM {target} AS target WHERE {live}"
+                ),
+                apply,
+                max_deleted_percent: propagation.max_deleted_percent,
+            }
+        });
 
-/// Create a table in Postgres if it doesn't already exist.
-pub async fn create_table_if_not_exists(
-    df: &DataFrame,
-    pool: &Pool<Postgres>,
-    schema: &str,
-    table: &str,
-    primary_key: Option<&str>,
-) -> Result<()> {
-    // Build `"col" TYPE` items
-    let cols: Vec<String> = df
-        .get_columns()
-        .iter()
-        .map(|s| -> Result<String> {
-            let pg = polars_to_postgres_dtype(s.dtype())?;
-            Ok(format!("{} {}", q(s.name()), pg))
+        Ok(Self {
+            lock: None,
+            create_table,
+            create_stage: Some(create_stage),
+            copy,
+            upsert: Some(insert_sql),
+            key: Some(pk.to_string()),
+            deletes,
         })
-        .collect::<Result<_>>()?;
-
-    let pk_clause = primary_key
-        .map(|pk| format!(", PRIMARY KEY ({})", q(pk)))
-        .unwrap_or_default();
+    }
 
-    let sql = format!(
-        "CREATE TABLE IF NOT EXISTS {}.{} ({cols}{pk})",
-        q(schema),
-        q(table),
-        cols = cols.join(", "),
-        pk = pk_clause
-    );
+    /// Serialize writers to `schema.table` with a transaction-scoped
+    /// advisory lock keyed on the table name.
+    fn with_advisory_lock(mut self, schema: &str, table: &str) -> Self {
+        let key = literal(&format!("{}.{}", q(schema), q(table)));
+        self.lock = Some(format!("SELECT pg_advisory_xact_lock(hashtextextended({key}, 0))"));
+        self
+    }
 
-    sqlx::query(&sql).execute(pool).await?;
-    Ok(())
+    fn into_vec(self) -> Vec<String> {
+        let (count, apply) = self.deletes.map(|d| (d.count, d.apply)).unzip();
+        self.create_table
+            .map(TableDdl::into_vec)
+            .unwrap_or_default()
+            .into_iter()
+            .chain(self.lock)
+            .chain(
+                [self.create_stage, Some(self.copy), count, self.upsert, apply]
+                    .into_iter()
+                    .flatten(),
+            )
+            .collect()
+    }
 }
 
 // ============================================================================
@@ -172,9 +1629,78 @@ pub async fn create_table_if_not_exists(
 
 /// Map Polars `DataType` to PostgreSQL type string.
 pub fn polars_to_postgres_dtype(dtype: &DataType) -> Result<String> {
+    Ok(mapped_postgres_dtype(dtype).unwrap_or(Cow::Borrowed("text")).into_owned())
+}
+
+/// A Postgres type name as written in DDL: a (possibly schema-qualified or
+/// quoted) name, the extra words of multi-word types such as `double
+/// precision` or `interval day to second`, numeric modifiers, then array
+/// brackets.
+static POSTGRES_TYPE: LazyLock<Regex> = LazyLock::new(|| {
+    let ident = r#"(?:[A-Za-z_][A-Za-z0-9_$]*|"(?:[^"]|"")+")"#;
+    let word = r"(?i:precision|varying|with|without|time|zone|year|month|day|hour|minute|second|to)";
+    let modifiers = r"(?:\s*\(\s*-?\d+\s*(?:,\s*-?\d+\s*)*\))?";
+    Regex::new(&format!(
+        r"^\s*{ident}(?:\.{ident})?{modifiers}(?:\s+{word}{modifiers})*(?:\s*\[\s*\d*\s*\])*\s*$"
+    ))
+    .expect("valid type pattern")
+});
+
+/// The Postgres type of each column: the override from `column_types`, or the
+/// mapped type. In strict mode, dtypes without a mapping of their own are an
+/// error instead of `text`.
+pub fn postgres_column_types(
+    df: &DataFrame,
+    options: &PostgresTableOptions,
+) -> Result<Vec<(String, String)>> {
+    if let Some(column) = options
+        .column_types
+        .keys()
+        .find(|c| df.get_column_index(c).is_none())
+    {
+        return Err(Error::Config(format!("column type given for unknown column '{column}'")));
+    }
+
+    let mut unmapped = Vec::new();
+    let mut types = Vec::with_capacity(df.width());
+    for column in df.get_columns() {
+        let name = column.name().to_string();
+        let pg = match options.column_types.get(&name) {
+            Some(pg) if !POSTGRES_TYPE.is_match(pg) => {
+                return Err(Error::Config(format!("invalid Postgres type '{pg}' for '{name}'")));
+            }
+            Some(pg) => pg.clone(),
+            None => match mapped_postgres_dtype(column.dtype()) {
+                Some(pg) => pg.into_owned(),
+                None if options.strict_types => {
+                    unmapped.push(format!("{name} ({})", column.dtype()));
+                    continue;
+                }
+                None => "text".to_string(),
+            },
+        };
+        types.push((name, pg));
+    }
+    if !unmapped.is_empty() {
+        return Err(Error::Schema(format!(
+            "no Postgres type for {}; declare them in column_types",
+            unmapped.join(", ")
+        )));
+    }
+    Ok(types)
+}
+
+/// `None` for dtypes that only fit the `text` catch-all.
+fn mapped_postgres_dtype(dtype: &DataType) -> Option<Cow<'static, str>> {
     use DataType::*;
 
     let ty = match dtype {
+        // Unconstrained `numeric` when polars does not know the precision.
+        Decimal(Some(precision), scale) => {
+            return Some(Cow::Owned(format!("numeric({precision},{})", scale.unwrap_or(0))));
+        }
+        Decimal(None, _) => "numeric",
+
         // Integers (Postgres has no unsigned types)
         Int8 | Int16 => "smallint",
         Int32 => "int4",
@@ -203,9 +1729,6 @@ pub fn polars_to_postgres_dtype(dtype: &DataType) -> Result<String> {
         }
         Duration(_) => "interval",
 
-        // Decimals
-        Decimal => "numeric",
-
         // Nested / Complex → JSONB
         List(_) | Struct(_) => "jsonb",
 
@@ -213,136 +1736,117 @@ pub fn polars_to_postgres_dtype(dtype: &DataType) -> Result<String> {
         Categorical(_, _) | Enum(_, _) | Null => "text",
 
         // Catch-all
-        _ => "text",
+        _ => return None,
     };
 
-    Ok(ty.to_string())
+    Some(Cow::Borrowed(ty))
 }
 
 // ============================================================================
 // Save Data to Postgres
 // ============================================================================
 
+/// Returns the upsert's row counts, or `None` for appends.
 async fn save_data_to_postgres(
     df: &mut DataFrame,
     pool: &Pool<Postgres>,
-    schema: &str,
-    table: &str,
-    auto_create: bool,
-    upsert: bool,
-    primary_key: Option<&str>,
-) -> Result<()> {
-    // 1) Create table if needed
-    if auto_create {
-        create_table_if_not_exists(df, pool, schema, table, primary_key).await?;
+    statements: &PostgresStatements,
+) -> Result<Option<UpsertCounts>> {
+    // 1) Create schema, table, indexes and comments if missing, in a short
+    // transaction of their own: DDL locks taken inside the data transaction
+    // would be held until commit and can deadlock concurrent writers.
+    if let Some(ddl) = &statements.create_table {
+        ddl.apply(pool, statements.lock.as_deref()).await?;
     }
 
-    // Collect column names once, in frame order
-    let cols_df: Vec<String> = df
-        .get_column_names_owned()
-        .iter()
-        .map(|c| c.to_string())
-        .collect();
-    let cols_quoted: Vec<String> = cols_df.iter().map(|c| q(c)).collect();
+    // 2) One data transaction: the lock (if any) is held until commit, and a
+    // failed write leaves no partial rows.
+    let mut tx = pool.begin().await?;
+    if let Some(lock) = &statements.lock {
+        sqlx::query(lock).execute(&mut *tx).await?;
+    }
 
-    if upsert {
+    if let (Some(create_stage), Some(insert_sql), Some(key)) =
+        (&statements.create_stage, &statements.upsert, &statements.key)
+    {
         // ────────────────────────────────────────────────────────────────────
         // UPSERT path: stage -> copy -> insert on conflict
         // ────────────────────────────────────────────────────────────────────
-        let pk = match primary_key {
-            Some(pk) => pk,
-            None => {
-                return Err(std::io::Error::new(
-                    std::io::ErrorKind::InvalidInput,
-                    "upsert requested but no primary key was provided",
-                )
-                .into());
-            }
-        };
-
-        // Unique, process-local stage name
-        let ts = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_millis();
-        let stage = format!("stage_{}_{}", table.replace('.', "_"), ts);
-
-        // Use a transaction so stage + insert is atomic
-        let mut tx = pool.begin().await?;
-
-        // Create TEMP stage with same structure
-        let create_stage = format!(
-            "CREATE TEMP TABLE {stage} (LIKE {schema}.{table} INCLUDING ALL) ON COMMIT DROP",
-            stage = q(&stage),
-            schema = q(schema),
-            table = q(table),
-        );
-        sqlx::query(&create_stage).execute(&mut *tx).await?;
-
-        // COPY into stage
-        let copy_sql = format!(
-            "COPY {stage} ({cols}) FROM STDIN WITH (FORMAT csv)",
-            stage = q(&stage),
-            cols = cols_quoted.join(", "),
-        );
-        let conn = tx.acquire().await?; // -> &mut PgConnection
-        let mut writer = conn.copy_in_raw(&copy_sql).await?;
-
-        // Stream df -> csv bytes -> write
-        const CHUNK: usize = 100_000;
-        let height = df.height();
-        for start in (0..height).step_by(CHUNK) {
-            let len = (height - start).min(CHUNK);
-            let chunk = df.slice(start as i64, len);
-            let bytes = df_chunk_to_csv_bytes(chunk).await?;
-            writer.send(bytes).await?;
+        let df = dedupe_by_key(df, key)?;
+        sqlx::query(create_stage).execute(&mut *tx).await?;
+        copy_frame(tx.acquire().await?, &statements.copy, &df, "upsert").await?;
+        // Checked before anything changes; dropping `tx` rolls back the stage.
+        if let Some(deletes) = &statements.deletes {
+            let (missing, live): (i64, i64) =
+                sqlx::query_as(&deletes.count).fetch_one(&mut *tx).await?;
+            deletes.check(missing, live)?;
         }
-        writer.finish().await?;
-
-        // Build UPDATE clause for non-PK columns
-        let non_pk_sets = cols_df
-            .iter()
-            .filter(|c| c.as_str() != pk)
-            .map(|c| format!("{} = EXCLUDED.{}", q(c), q(c)))
-            .collect::<Vec<_>>()
-            .join(", ");
-
-        let insert_sql = format!(
-            "INSERT INTO {schema}.{table} ({cols})
-             SELECT {cols} FROM {stage}
-             ON CONFLICT ({pk}) DO UPDATE SET {sets}",
-            schema = q(schema),
-            table = q(table),
-            cols = cols_quoted.join(", "),
-            stage = q(&stage),
-            pk = q(pk),
-            sets = non_pk_sets,
-        );
-        sqlx::query(&insert_sql).execute(&mut *tx).await?;
+        let (inserted, updated): (i64, i64) =
+            sqlx::query_as(insert_sql).fetch_one(&mut *tx).await?;
+        let deleted = match &statements.deletes {
+            Some(deletes) => sqlx::query(&deletes.apply).execute(&mut *tx).await?.rows_affected(),
+            None => 0,
+        };
         tx.commit().await?;
+
+        let (inserted, updated) = (inserted as u64, updated as u64);
+        Ok(Some(UpsertCounts {
+            inserted,
+            updated,
+            unchanged: (df.height() as u64).saturating_sub(inserted + updated),
+            deleted,
+        }))
     } else {
         // ────────────────────────────────────────────────────────────────────
         // Append path: direct COPY into target
         // ────────────────────────────────────────────────────────────────────
-        let copy_sql = format!(
-            "COPY {schema}.{table} ({cols}) FROM STDIN WITH (FORMAT csv)",
-            schema = q(schema),
-            table = q(table),
-            cols = cols_quoted.join(", "),
-        );
-        let mut writer = pool.copy_in_raw(&copy_sql).await?;
+        copy_frame(tx.acquire().await?, &statements.copy, df, "append").await?;
+        tx.commit().await?;
+        Ok(None)
+    }
+}
+
+/// Keep the last row per key: `INSERT ... ON CONFLICT` fails with "cannot
+/// affect row a second time" when one statement carries a key twice.
+fn dedupe_by_key(df: &DataFrame, key: &str) -> Result<DataFrame> {
+    let deduped = df.unique_stable(Some(&[key.to_string()]), UniqueKeepStrategy::Last, None)?;
+    let dropped = df.height() - deduped.height();
+    if dropped > 0 {
+        warn!(key, dropped, "dropped rows with duplicate keys before upsert");
+    }
+    Ok(deduped)
+}
 
-        const CHUNK: usize = 100_000;
-        let height = df.height();
-        for start in (0..height).step_by(CHUNK) {
-            let len = (height - start).min(CHUNK);
-            let chunk = df.slice(start as i64, len);
+/// Stream `df` through `copy` (a `COPY ... FROM STDIN` statement) in chunks.
+async fn copy_frame(
+    conn: &mut PgConnection,
+    copy: &str,
+    df: &DataFrame,
+    mode: &str,
+) -> Result<()> {
+    let copy_started = Instant::now();
+    let mut writer = conn.copy_in_raw(copy).await?;
+
+    // Stream df -> csv bytes -> write
+    const CHUNK: usize = 100_000;
+    let height = df.height();
+    for (idx, start) in (0..height).step_by(CHUNK).enumerate() {
+        let len = (height - start).min(CHUNK);
+        let chunk = df.slice(start as i64, len);
+        let span = info_span!("sink.copy_chunk", chunk = idx, rows = len);
+        async {
             let bytes = df_chunk_to_csv_bytes(chunk).await?;
             writer.send(bytes).await?;
+            Ok::<_, Error>(())
         }
-        writer.finish().await?;
+        .instrument(span)
+        .await?;
     }
-
+    writer.finish().await?;
+    metrics()
+        .copy_duration
+        .with_label_values(&[mode])
+        .observe(copy_started.elapsed().as_secs_f64());
     Ok(())
 }
 
@@ -350,10 +1854,18 @@ async fn save_data_to_postgres(
 // CSV Chunk Conversion
 // ============================================================================
 
-/// Build CSV bytes for a DataFrame *chunk* off the main thread.
+/// Build CSV bytes for a DataFrame *chunk* off the main thread. The CSV
+/// writer has no decimal support, so decimals are written as their text form,
+/// which `numeric` parses exactly.
 async fn df_chunk_to_csv_bytes(chunk: DataFrame) -> Result<Vec<u8>> {
     tokio::task::spawn_blocking(move || {
         let mut tmp = chunk.clone(); // CsvWriter needs &mut DataFrame
+        for name in chunk.get_column_names_owned() {
+            let column = chunk.column(&name)?;
+            if matches!(column.dtype(), DataType::Decimal(_, _)) {
+                tmp.with_column(column.cast(&DataType::String)?)?;
+            }
+        }
         let mut buf = Vec::with_capacity(tmp.height().saturating_mul(64));
         CsvWriter::new(&mut buf)
             .include_header(false) // COPY expects no header
@@ -363,4 +1875,245 @@ async fn df_chunk_to_csv_bytes(chunk: DataFrame) -> Result<Vec<u8>> {
     })
     .await
     .expect("join blocking CSV task")
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn types(df: &DataFrame, options: &PostgresTableOptions) -> Result<Vec<String>> {
+        Ok(postgres_column_types(df, options)?.into_iter().map(|(_, pg)| pg).collect())
+    }
+
+    #[test]
+    fn decimals_map_to_numeric() {
+        let amount = Series::new("amount".into(), [1.5f64])
+            .cast(&DataType::Decimal(Some(18), Some(4)))
+            .unwrap();
+        let df = DataFrame::new(vec![amount.into()]).unwrap();
+        let options = PostgresTableOptions::default().strict_types(true);
+        assert_eq!(types(&df, &options).unwrap(), ["numeric(18,4)"]);
+        assert_eq!(mapped_postgres_dtype(&DataType::Decimal(None, None)).unwrap(), "numeric");
+    }
+
+    #[test]
+    fn column_type_overrides_must_be_type_names() {
+        let df = df!("v" => ["x"]).unwrap();
+        for pg in [
+            "uuid",
+            "numeric(18,4)",
+            "character varying(255)",
+            "timestamp(3) with time zone",
+            "public.mood",
+            r#""Mood""#,
+            "int4[]",
+            "double precision[][]",
+            "interval day to second(0)",
+        ] {
+            let options = PostgresTableOptions::default().column_type("v", pg);
+            assert_eq!(types(&df, &options).unwrap(), [pg], "{pg}");
+        }
+        for pg in [
+            "",
+            "text; DROP TABLE users",
+            "text DEFAULT 'x'",
+            "int4 CHECK (v > 0)",
+            "int4 not null",
+            "text -- comment",
+            r#""a" ; "b""#,
+        ] {
+            let options = PostgresTableOptions::default().column_type("v", pg);
+            assert!(types(&df, &options).is_err(), "{pg}");
+        }
+    }
+
+    fn pages() -> DataFrame {
+        df!("id" => [1i64, 2], "title" => ["a", "b"], "body" => ["x", "y"]).unwrap()
+    }
+
+    fn upsert_statements(
+        df: &DataFrame,
+        table: &str,
+        changes: ChangeDetection,
+        deletes: Option<DeletePropagation>,
+    ) -> Result<PostgresStatements> {
+        let upsert = UpsertOptions {
+            changes: &changes,
+            deletes: deletes.as_ref(),
+        };
+        let options = PostgresTableOptions::default();
+        PostgresStatements::build(df, "public", table, false, &options, Some(upsert), Some("id"))
+    }
+
+    /// The quoted stage table name, from its `COPY`.
+    fn stage(statements: &PostgresStatements) -> &str {
+        let rest = statements.copy.strip_prefix("COPY ").unwrap();
+        &rest[..rest.find(" (").unwrap()]
+    }
+
+    #[test]
+    fn each_upsert_gets_its_own_stage() {
+        let df = pages();
+        let first = upsert_statements(&df, "pages", ChangeDetection::Always, None).unwrap();
+        let second = upsert_statements(&df, "pages", ChangeDetection::Always, None).unwrap();
+        assert_ne!(stage(&first), stage(&second));
+        assert!(stage(&first).starts_with(r#""stage_pages_"#));
+        assert_eq!(
+            first.create_stage.as_deref().unwrap(),
+            format!(
+                r#"CREATE TEMP TABLE {} (LIKE "public"."pages" INCLUDING ALL) ON COMMIT DROP"#,
+                stage(&first)
+            )
+        );
+
+        let long = "p".repeat(100);
+        let statements = upsert_statements(&df, &long, ChangeDetection::Always, None).unwrap();
+        assert!(stage(&statements).trim_matches('"').len() <= 63);
+    }
+
+    #[test]
+    fn upserts_insert_deduplicated_rows_in_key_order() {
+        let statements = upsert_statements(&pages(), "pages", ChangeDetection::Always, None)
+            .unwrap()
+            .with_advisory_lock("public", "pages");
+        let upsert = statements.upsert.as_deref().unwrap();
+        assert!(upsert.contains(&format!(
+            r#"SELECT "id", "title", "body" FROM {} ORDER BY "id""#,
+            stage(&statements)
+        )));
+        assert!(upsert.contains(r#"ON CONFLICT ("id") DO UPDATE SET"#));
+        assert_eq!(statements.key.as_deref(), Some("id"));
+        assert_eq!(
+            statements.lock.as_deref(),
+            Some(r#"SELECT pg_advisory_xact_lock(hashtextextended('"public"."pages"', 0))"#)
+        );
+
+        let df = df!("id" => [1i64, 2, 1], "title" => ["old", "b", "new"]).unwrap();
+        let deduped = dedupe_by_key(&df, "id").unwrap();
+        assert_eq!(deduped.column("title").unwrap().str().unwrap().get(0), Some("b"));
+        assert_eq!(deduped.column("title").unwrap().str().unwrap().get(1), Some("new"));
+    }
+
+    #[test]
+    fn upserts_need_a_primary_key() {
+        let changes = ChangeDetection::Always;
+        let upsert = UpsertOptions {
+            changes: &changes,
+            deletes: None,
+        };
+        let options = PostgresTableOptions::default();
+        let df = pages();
+        assert!(PostgresStatements::build(&df, "public", "pages", false, &options, Some(upsert), None)
+            .is_err());
+    }
+
+    #[test]
+    fn column_change_detection_compares_non_key_columns() {
+        let statements =
+            upsert_statements(&pages(), "pages", ChangeDetection::Columns, None).unwrap();
+        assert!(statements.upsert.as_deref().unwrap().contains(
+            r#"DO UPDATE SET "title" = EXCLUDED."title", "body" = EXCLUDED."body" WHERE ROW(target."title", target."body") IS DISTINCT FROM ROW(EXCLUDED."title", EXCLUDED."body")"#
+        ));
+    }
+
+    #[test]
+    fn hash_change_detection_compares_the_hash_column() {
+        let df = df!("id" => [1i64], "title" => ["a"], "row_hash" => ["h"]).unwrap();
+        let hash = |c: &str| ChangeDetection::Hash(c.to_string());
+        let statements = upsert_statements(&df, "pages", hash("row_hash"), None).unwrap();
+        assert!(statements
+            .upsert
+            .as_deref()
+            .unwrap()
+            .contains(r#"WHERE target."row_hash" IS DISTINCT FROM EXCLUDED."row_hash""#));
+
+        assert!(upsert_statements(&df, "pages", hash("missing"), None).is_err());
+        assert!(upsert_statements(&df, "pages", hash("id"), None).is_err());
+    }
+
+    #[test]
+    fn upserts_count_inserted_and_updated_rows() {
+        let statements =
+            upsert_statements(&pages(), "pages", ChangeDetection::Always, None).unwrap();
+        let upsert = statements.upsert.as_deref().unwrap();
+        assert!(!upsert.contains("WHERE target."));
+        assert!(upsert.contains("RETURNING (xmax = 0) AS inserted)"));
+        assert!(upsert.ends_with(
+            "SELECT count(*) FILTER (WHERE inserted), count(*) FILTER (WHERE NOT inserted) FROM upserted"
+        ));
+
+        // Nothing to update when the frame holds only the key.
+        let keys = df!("id" => [1i64]).unwrap();
+        let statements = upsert_statements(&keys, "pages", ChangeDetection::Columns, None).unwrap();
+        assert!(statements.upsert.as_deref().unwrap().contains(r#"ON CONFLICT ("id") DO NOTHING"#));
+    }
+
+    #[test]
+    fn hard_deletes_remove_rows_missing_from_the_stage() {
+        let deletes = DeletePropagation::hard().max_deleted_percent(10.0);
+        let statements =
+            upsert_statements(&pages(), "pages", ChangeDetection::Always, Some(deletes)).unwrap();
+        let missing = format!(
+            r#"NOT EXISTS (SELECT 1 FROM {} AS staged WHERE staged."id" = target."id")"#,
+            stage(&statements)
+        );
+        let d = statements.deletes.as_ref().unwrap();
+        assert_eq!(
+            d.count.split_whitespace().collect::<Vec<_>>().join(" "),
+            format!(r#"SELECT count(*) FILTER (WHERE {missing}), count(*) FROM "public"."pages" AS target WHERE TRUE"#)
+        );
+        assert_eq!(d.apply, format!(r#"DELETE FROM "public"."pages" AS target WHERE {missing}"#));
+
+        // Counted before the upsert, applied after it.
+        let (count, apply) = (d.count.clone(), d.apply.clone());
+        let upsert = statements.upsert.clone().unwrap();
+        let order = statements.into_vec();
+        let at = |sql: &str| order.iter().position(|s| s == sql).unwrap();
+        assert!(at(&count) < at(&upsert) && at(&upsert) < at(&apply));
+    }
+
+    #[test]
+    fn delete_threshold_is_a_share_of_live_rows() {
+        let deletes = DeletePropagation::hard().max_deleted_percent(10.0);
+        let statements =
+            upsert_statements(&pages(), "pages", ChangeDetection::Always, Some(deletes)).unwrap();
+        let d = statements.deletes.unwrap();
+        assert!(d.check(0, 0).is_ok());
+        assert!(d.check(10, 100).is_ok());
+        assert!(matches!(d.check(11, 100), Err(Error::Quality(_))));
+    }
+
+    #[test]
+    fn soft_deletes_mark_missing_rows_and_revive_returning_ones() {
+        let deletes = DeletePropagation::soft("deleted_at");
+        let statements =
+            upsert_statements(&pages(), "pages", ChangeDetection::Columns, Some(deletes)).unwrap();
+        let d = statements.deletes.as_ref().unwrap();
+        assert!(d.count.ends_with(r#"WHERE target."deleted_at" IS NULL"#));
+        assert!(d.apply.starts_with(
+            r#"UPDATE "public"."pages" AS target SET "deleted_at" = now() WHERE target."deleted_at" IS NULL AND NOT EXISTS"#
+        ));
+        assert!(statements.upsert.as_deref().unwrap().contains(
+            r#""deleted_at" = NULL WHERE (ROW(target."title", target."body") IS DISTINCT FROM ROW(EXCLUDED."title", EXCLUDED."body")) OR target."deleted_at" IS NOT NULL"#
+        ));
+
+        // auto_create adds the column to tables that lack it.
+        let changes = ChangeDetection::Always;
+        let deletes = DeletePropagation::soft("deleted_at");
+        let upsert = UpsertOptions {
+            changes: &changes,
+            deletes: Some(&deletes),
+        };
+        let options = PostgresTableOptions::default();
+        let df = pages();
+        let statements =
+            PostgresStatements::build(&df, "public", "pages", true, &options, Some(upsert), Some("id"))
+                .unwrap();
+        let ddl = statements.create_table.unwrap();
+        assert!(ddl.columns.contains(&(
+            "deleted_at".to_string(),
+            r#"ALTER TABLE "public"."pages" ADD COLUMN IF NOT EXISTS "deleted_at" timestamptz"#
+                .to_string()
+        )));
+    }
+}
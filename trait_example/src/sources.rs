@@ -1,20 +1,73 @@
-use std::{borrow::Cow, collections::HashMap, io::Cursor};
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    io::{BufRead, BufReader, Cursor, Lines},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
 
 use async_trait::async_trait;
 use reqwest::{
     header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE},
-    Client, RequestBuilder,
+    Client, RequestBuilder, Response, StatusCode,
 };
-use polars::prelude::*;
+use polars::{functions::concat_df_diagonal, prelude::*};
+use sqlx::{Pool, Sqlite};
+use tokio::sync::mpsc::Sender;
+use tracing::{field, info_span, Instrument};
 
 use crate::errors::Result;
+use crate::metrics::metrics;
+use crate::sqlite::{read_sqlite, stream_sqlite};
+use crate::utils::collect_lazy;
+
+/// A source shared between jobs, pipelines and SQL stages.
+pub type SharedSource<'a> = Arc<dyn Source + Send + Sync + 'a>;
 
 /// A data source that can load a Polars `DataFrame`.
 #[async_trait]
 pub trait Source {
     async fn load_data(&self) -> Result<DataFrame>;
+
+    /// Short label used for metrics and logs.
+    fn kind(&self) -> &'static str {
+        "custom"
+    }
+
+    /// A lazy plan over the source. Defaults to loading eagerly; file sources
+    /// scan instead so filters and projections are pushed into the reader.
+    async fn scan(&self) -> Result<LazyFrame> {
+        Ok(self.load_data().await?.lazy())
+    }
+
+    /// Send the data as batches of about `batch_size` rows. `send` waits
+    /// while the channel is full, so a slow consumer holds the source back.
+    ///
+    /// Defaults to loading everything and slicing; sources that can read
+    /// incrementally override this.
+    async fn stream(&self, batch_size: usize, tx: Sender<DataFrame>) -> Result<()> {
+        let df = self.load_data().await?;
+        for start in (0..df.height()).step_by(batch_size.max(1)) {
+            if tx.send(df.slice(start as i64, batch_size)).await.is_err() {
+                break; // consumer gone; its error is reported instead
+            }
+        }
+        Ok(())
+    }
 }
 
+/// Offset/limit paging for HTTP sources: the offset parameter advances by
+/// `page_size` until a page returns fewer rows than that.
+#[derive(Clone, Debug)]
+pub struct Pagination<'a> {
+    pub offset_param: Cow<'a, str>,
+    pub limit_param: Cow<'a, str>,
+    pub page_size: usize,
+}
+
+// Built once per job, so the size of the HTTP variant doesn't matter.
+#[allow(clippy::large_enum_variant)]
 #[derive(Clone, Debug)]
 pub enum SourceKind<'a> {
     Http {
@@ -23,6 +76,18 @@ pub enum SourceKind<'a> {
         query: Option<HashMap<Cow<'a, str>, Cow<'a, str>>>,
         bearer_token: Option<Cow<'a, str>>,
         standard_auth: Option<(Cow<'a, str>, Cow<'a, str>)>,
+        max_retries: u32,
+        /// Read records from this field of a JSON object response.
+        records_field: Option<Cow<'a, str>>,
+        pagination: Option<Pagination<'a>>,
+    },
+    Csv(Cow<'a, str>),
+    Parquet(Cow<'a, str>),
+    NdJson(Cow<'a, str>),
+    /// Rows of a query against a SQLite database.
+    Sqlite {
+        pool: Arc<Pool<Sqlite>>,
+        query: Cow<'a, str>,
     },
 }
 
@@ -36,6 +101,9 @@ impl<'a> SourceKind<'a> {
             query: None,
             bearer_token: None,
             standard_auth: None,
+            max_retries: 0,
+            records_field: None,
+            pagination: None,
         }
     }
 
@@ -53,6 +121,9 @@ impl<'a> SourceKind<'a> {
             query,
             bearer_token,
             standard_auth,
+            max_retries: 0,
+            records_field: None,
+            pagination: None,
         }
     }
 
@@ -60,30 +131,345 @@ impl<'a> SourceKind<'a> {
     pub fn http(url: impl Into<Cow<'a, str>>) -> HttpBuilder<'a> {
         HttpBuilder::new(url)
     }
+
+    /// Scan a CSV file (with header).
+    pub fn read_csv(path: impl Into<Cow<'a, str>>) -> Self {
+        Self::Csv(path.into())
+    }
+
+    /// Scan a Parquet file.
+    pub fn read_parquet(path: impl Into<Cow<'a, str>>) -> Self {
+        Self::Parquet(path.into())
+    }
+
+    /// Scan a newline-delimited JSON file.
+    pub fn read_ndjson(path: impl Into<Cow<'a, str>>) -> Self {
+        Self::NdJson(path.into())
+    }
+
+    /// Run `query` against SQLite, e.g. `SELECT * FROM pages`.
+    pub fn read_sqlite(pool: Arc<Pool<Sqlite>>, query: impl Into<Cow<'a, str>>) -> Self {
+        Self::Sqlite {
+            pool,
+            query: query.into(),
+        }
+    }
 }
 
 #[async_trait]
 impl<'a> Source for SourceKind<'a> {
+    fn kind(&self) -> &'static str {
+        match self {
+            SourceKind::Http { .. } => "http",
+            SourceKind::Csv(_) => "csv",
+            SourceKind::Parquet(_) => "parquet",
+            SourceKind::NdJson(_) => "ndjson",
+            SourceKind::Sqlite { .. } => "sqlite",
+        }
+    }
+
     async fn load_data(&self) -> Result<DataFrame> {
         match self {
+            SourceKind::Http { pagination: None, .. } => self.fetch_page(0).await,
+            SourceKind::Http {
+                pagination: Some(paging),
+                ..
+            } => {
+                let mut pages = Vec::new();
+                for page in 0.. {
+                    let df = self.fetch_page(page).await?;
+                    let last = df.height() < paging.page_size;
+                    pages.push(df);
+                    if last {
+                        break;
+                    }
+                }
+                Ok(concat_df_diagonal(&pages)?)
+            }
+            SourceKind::Csv(_) | SourceKind::Parquet(_) | SourceKind::NdJson(_) => {
+                collect_lazy(self.scan().await?).await
+            }
+            SourceKind::Sqlite { pool, query } => read_sqlite(pool, query).await,
+        }
+    }
+
+    async fn scan(&self) -> Result<LazyFrame> {
+        let lf = match self {
+            SourceKind::Csv(path) => LazyCsvReader::new(PlPath::new(path))
+                .with_has_header(true)
+                .finish()?,
+            SourceKind::Parquet(path) => {
+                LazyFrame::scan_parquet(PlPath::new(path), ScanArgsParquet::default())?
+            }
+            SourceKind::NdJson(path) => LazyJsonLineReader::new(PlPath::new(path)).finish()?,
+            SourceKind::Http { .. } | SourceKind::Sqlite { .. } => self.load_data().await?.lazy(),
+        };
+        Ok(lf)
+    }
+
+    async fn stream(&self, batch_size: usize, tx: Sender<DataFrame>) -> Result<()> {
+        match self {
+            // One batch per page; the page size wins over `batch_size`.
             SourceKind::Http {
-                url,
-                headers,
-                query,
-                bearer_token,
-                standard_auth,
+                pagination: Some(paging),
+                ..
             } => {
-                let req = http_builder(
-                    url.clone(),
-                    headers.clone(),
-                    query.clone(),
-                    bearer_token.clone(),
-                    standard_auth.clone(),
-                )?;
-                http_request_to_df(req).await
+                for page in 0.. {
+                    let df = self.fetch_page(page).await?;
+                    let last = df.height() < paging.page_size;
+                    // An empty page has no columns; it only ends the stream.
+                    if (df.height() > 0 && tx.send(df).await.is_err()) || last {
+                        break;
+                    }
+                }
+            }
+            SourceKind::Http { pagination: None, .. } => {
+                let df = self.load_data().await?;
+                for start in (0..df.height()).step_by(batch_size.max(1)) {
+                    if tx.send(df.slice(start as i64, batch_size)).await.is_err() {
+                        break;
+                    }
+                }
+            }
+            // One pass over the file on a blocking thread.
+            SourceKind::Csv(path) | SourceKind::Parquet(path) | SourceKind::NdJson(path) => {
+                let path = PathBuf::from(path.as_ref());
+                let read = match self {
+                    SourceKind::Csv(_) => stream_csv,
+                    SourceKind::Parquet(_) => stream_parquet,
+                    _ => stream_ndjson,
+                };
+                let mut batches = Batches::new(batch_size, tx);
+                tokio::task::spawn_blocking(move || {
+                    read(&path, batch_size.max(1), &mut batches)?;
+                    batches.finish()
+                })
+                .await??;
+            }
+            // Paged, so the connection is free for sinks between batches.
+            SourceKind::Sqlite { pool, query } => {
+                stream_sqlite(pool, query, batch_size, tx).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a> SourceKind<'a> {
+    /// Fetch one page of an HTTP source; page `0` is the only page when
+    /// pagination is off.
+    async fn fetch_page(&self, page: usize) -> Result<DataFrame> {
+        let SourceKind::Http {
+            url,
+            headers,
+            query,
+            bearer_token,
+            standard_auth,
+            max_retries,
+            records_field,
+            pagination,
+        } = self
+        else {
+            return self.load_data().await;
+        };
+
+        let mut query = query.clone();
+        if let Some(paging) = pagination {
+            let q = query.get_or_insert_with(HashMap::new);
+            q.insert(
+                paging.offset_param.clone(),
+                (page * paging.page_size).to_string().into(),
+            );
+            q.insert(paging.limit_param.clone(), paging.page_size.to_string().into());
+        }
+
+        let req = http_builder(
+            url.clone(),
+            headers.clone(),
+            query,
+            bearer_token.clone(),
+            standard_auth.clone(),
+        )?;
+        let span = info_span!(
+            "source.fetch_page",
+            source = "http",
+            page,
+            status = field::Empty,
+            bytes = field::Empty,
+            rows = field::Empty,
+        );
+        let df = http_request_to_df(req, *max_retries, records_field.as_deref())
+            .instrument(span.clone())
+            .await?;
+        span.record("rows", df.height());
+        Ok(df)
+    }
+}
+
+// ============================================================================
+// Batched file readers
+// ============================================================================
+
+/// Cuts frames of any size into batches of exactly `size` rows (the last one
+/// may be shorter) and sends them from a blocking thread.
+struct Batches {
+    size: usize,
+    pending: Vec<DataFrame>,
+    rows: usize,
+    tx: Sender<DataFrame>,
+}
+
+impl Batches {
+    fn new(size: usize, tx: Sender<DataFrame>) -> Self {
+        Self {
+            size: size.max(1),
+            pending: Vec::new(),
+            rows: 0,
+            tx,
+        }
+    }
+
+    /// Queue `df`; `false` once the receiver is gone and reading can stop.
+    fn push(&mut self, df: DataFrame) -> Result<bool> {
+        self.rows += df.height();
+        self.pending.push(df);
+        while self.rows >= self.size {
+            let all = self.take()?;
+            let rest = all.slice(self.size as i64, all.height() - self.size);
+            if !self.send(all.slice(0, self.size)) {
+                self.rows = 0;
+                return Ok(false);
+            }
+            self.rows = rest.height();
+            self.pending.push(rest);
+        }
+        Ok(true)
+    }
+
+    fn finish(mut self) -> Result<()> {
+        if self.rows > 0 {
+            let rest = self.take()?;
+            self.send(rest);
+        }
+        Ok(())
+    }
+
+    fn take(&mut self) -> Result<DataFrame> {
+        let pending = std::mem::take(&mut self.pending);
+        let mut df = concat_df_diagonal(&pending)?;
+        df.rechunk_mut();
+        Ok(df)
+    }
+
+    fn send(&self, df: DataFrame) -> bool {
+        self.tx.blocking_send(df).is_ok()
+    }
+}
+
+fn stream_csv(path: &Path, batch_size: usize, batches: &mut Batches) -> Result<()> {
+    let mut reader = CsvReadOptions::default()
+        .with_has_header(true)
+        .with_chunk_size(batch_size)
+        .try_into_reader_with_file_path(Some(path.to_path_buf()))?;
+    let mut reader = reader.batched_borrowed()?;
+    while let Some(chunks) = reader.next_batches(1)? {
+        for df in chunks {
+            if !batches.push(df)? {
+                return Ok(());
             }
         }
     }
+    Ok(())
+}
+
+/// Reads a row group at a time; the footer is read once.
+fn stream_parquet(path: &Path, _batch_size: usize, batches: &mut Batches) -> Result<()> {
+    let file = std::fs::File::open(path)?;
+    let metadata = ParquetReader::new(file.try_clone()?).get_metadata()?.clone();
+    let mut offset = 0;
+    for group in &metadata.row_groups {
+        let rows = group.num_rows();
+        let mut reader = ParquetReader::new(file.try_clone()?);
+        reader.set_metadata(metadata.clone());
+        let df = reader.with_slice(Some((offset, rows))).finish()?;
+        offset += rows;
+        if !batches.push(df)? {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Lines of a streamed NDJSON file its column types are inferred from.
+const NDJSON_SCHEMA_SAMPLE: usize = 10_000;
+
+/// Parses `batch_size` lines at a time with one schema, inferred from the
+/// first [`NDJSON_SCHEMA_SAMPLE`] lines, so every batch has the same columns
+/// and types. Columns that are null throughout the sample are read as text;
+/// columns first seen after it are dropped, and values that don't fit the
+/// inferred type (e.g. a float in an integer column) fail the read.
+fn stream_ndjson(path: &Path, batch_size: usize, batches: &mut Batches) -> Result<()> {
+    let Some(schema) = ndjson_schema(path)? else {
+        return Ok(());
+    };
+    let mut lines = BufReader::new(std::fs::File::open(path)?).lines();
+    loop {
+        let mut buf = Vec::new();
+        let n = read_lines(&mut lines, batch_size, &mut buf)?;
+        if n == 0 {
+            return Ok(());
+        }
+        let df = JsonLineReader::new(Cursor::new(buf))
+            .with_schema(schema.clone())
+            .finish()?;
+        if !batches.push(df)? || n < batch_size {
+            return Ok(());
+        }
+    }
+}
+
+/// Schema of the first lines of an NDJSON file, or `None` if it is empty.
+fn ndjson_schema(path: &Path) -> Result<Option<SchemaRef>> {
+    let mut lines = BufReader::new(std::fs::File::open(path)?).lines();
+    let mut buf = Vec::new();
+    if read_lines(&mut lines, NDJSON_SCHEMA_SAMPLE, &mut buf)? == 0 {
+        return Ok(None);
+    }
+    let sample = JsonLineReader::new(Cursor::new(buf))
+        .infer_schema_len(None)
+        .finish()?;
+    let schema = sample
+        .schema()
+        .iter()
+        .map(|(name, dtype)| match dtype {
+            // A Null column rejects any value; text accepts every scalar.
+            DataType::Null => Field::new(name.clone(), DataType::String),
+            dtype => Field::new(name.clone(), dtype.clone()),
+        })
+        .collect::<Schema>();
+    Ok(Some(Arc::new(schema)))
+}
+
+/// Append up to `limit` non-empty lines to `buf`; returns how many.
+fn read_lines(
+    lines: &mut Lines<BufReader<std::fs::File>>,
+    limit: usize,
+    buf: &mut Vec<u8>,
+) -> Result<usize> {
+    let mut n = 0;
+    for line in lines.by_ref() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        buf.extend_from_slice(line.as_bytes());
+        buf.push(b'\n');
+        n += 1;
+        if n == limit {
+            break;
+        }
+    }
+    Ok(n)
 }
 
 /// Ergonomic builder for `SourceKind::Http`.
@@ -94,6 +480,9 @@ pub struct HttpBuilder<'a> {
     query: Option<HashMap<Cow<'a, str>, Cow<'a, str>>>,
     bearer_token: Option<Cow<'a, str>>,
     standard_auth: Option<(Cow<'a, str>, Cow<'a, str>)>,
+    max_retries: u32,
+    records_field: Option<Cow<'a, str>>,
+    pagination: Option<Pagination<'a>>,
 }
 
 impl<'a> HttpBuilder<'a> {
@@ -104,6 +493,9 @@ impl<'a> HttpBuilder<'a> {
             query: None,
             bearer_token: None,
             standard_auth: None,
+            max_retries: 0,
+            records_field: None,
+            pagination: None,
         }
     }
 
@@ -133,6 +525,33 @@ impl<'a> HttpBuilder<'a> {
         self
     }
 
+    /// Retry connection errors, `429` and `5xx` responses up to `n` times.
+    pub fn retries(mut self, n: u32) -> Self {
+        self.max_retries = n;
+        self
+    }
+
+    /// Take records from `field` of an object response, e.g. `"results"`.
+    pub fn records_field(mut self, field: impl Into<Cow<'a, str>>) -> Self {
+        self.records_field = Some(field.into());
+        self
+    }
+
+    /// Page through results with offset/limit query parameters.
+    pub fn paginate(
+        mut self,
+        offset_param: impl Into<Cow<'a, str>>,
+        limit_param: impl Into<Cow<'a, str>>,
+        page_size: usize,
+    ) -> Self {
+        self.pagination = Some(Pagination {
+            offset_param: offset_param.into(),
+            limit_param: limit_param.into(),
+            page_size: page_size.max(1),
+        });
+        self
+    }
+
     pub fn build(self) -> SourceKind<'a> {
         SourceKind::Http {
             url: self.url,
@@ -140,6 +559,9 @@ impl<'a> HttpBuilder<'a> {
             query: self.query,
             bearer_token: self.bearer_token,
             standard_auth: self.standard_auth,
+            max_retries: self.max_retries,
+            records_field: self.records_field,
+            pagination: self.pagination,
         }
     }
 }
@@ -184,11 +606,54 @@ pub fn http_builder<'a>(
     Ok(req)
 }
 
+/// Send a request, retrying transient failures with exponential backoff.
+async fn send_with_retries(req: RequestBuilder, max_retries: u32) -> Result<Response> {
+    let mut attempt = 0;
+    loop {
+        // Streaming bodies cannot be cloned; those get a single attempt.
+        let Some(this_try) = req.try_clone() else {
+            return Ok(req.send().await?);
+        };
+
+        let reason = match this_try.send().await {
+            Ok(res) => {
+                let status = res.status();
+                metrics()
+                    .http_responses
+                    .with_label_values(&[status.as_str()])
+                    .inc();
+                if !(status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error())
+                    || attempt >= max_retries
+                {
+                    return Ok(res);
+                }
+                status.as_str().to_string()
+            }
+            Err(e) if (e.is_connect() || e.is_timeout()) && attempt < max_retries => {
+                "connect".to_string()
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        attempt += 1;
+        metrics().http_retries.with_label_values(&[&reason]).inc();
+        let backoff = Duration::from_millis(500 * 2u64.pow(attempt.min(6) - 1));
+        tracing::warn!("retrying request ({}/{}) after {:?}: {}", attempt, max_retries, backoff, reason);
+        tokio::time::sleep(backoff).await;
+    }
+}
+
 /// Fetch JSON/NDJSON and parse into a `DataFrame`, then flatten nested structs.
-pub async fn http_request_to_df(req: RequestBuilder) -> Result<DataFrame> {
-   
-    let res = req.send().await?.error_for_status()?;
-    let mut df = DataFrame::empty();
+///
+/// With `records_field`, an object response is replaced by that field's value.
+pub async fn http_request_to_df(
+    req: RequestBuilder,
+    max_retries: u32,
+    records_field: Option<&str>,
+) -> Result<DataFrame> {
+    let res = send_with_retries(req, max_retries).await?;
+    tracing::Span::current().record("status", res.status().as_u16());
+    let res = res.error_for_status()?;
 
     // own content-type before consuming the body
     let ctype = res
@@ -199,25 +664,36 @@ pub async fn http_request_to_df(req: RequestBuilder) -> Result<DataFrame> {
         .to_string();
 
     let bytes = res.bytes().await?;
+    metrics()
+        .bytes_fetched
+        .with_label_values(&["http"])
+        .inc_by(bytes.len() as u64);
+    tracing::Span::current().record("bytes", bytes.len());
 
     // NDJSON (one object per line)
     if ctype.contains("ndjson") || ctype.contains("jsonlines") {
-        df = JsonReader::new(Cursor::new(bytes))
+        let df = JsonReader::new(Cursor::new(bytes))
             .with_json_format(JsonFormat::JsonLines)
             .finish()?;
         return Ok(df);
     }
 
     // Regular JSON: array or single object → wrap as array
-    let val: serde_json::Value = serde_json::from_slice(&bytes)?;
+    let mut val: serde_json::Value = serde_json::from_slice(&bytes)?;
+    if let (Some(field), serde_json::Value::Object(obj)) = (records_field, &mut val) {
+        val = obj.remove(field).unwrap_or(serde_json::Value::Array(Vec::new()));
+    }
     let array_val = match val {
         serde_json::Value::Array(_) => val,
         serde_json::Value::Object(_) => serde_json::Value::Array(vec![val]),
         _ => serde_json::Value::Null,
     };
+    if array_val.as_array().is_some_and(|a| a.is_empty()) {
+        return Ok(DataFrame::empty());
+    }
 
     let arr_bytes = serde_json::to_vec(&array_val)?;
-    df = JsonReader::new(Cursor::new(arr_bytes))
+    let df = JsonReader::new(Cursor::new(arr_bytes))
         .with_json_format(JsonFormat::Json)
         .finish()?;
 
@@ -227,6 +703,7 @@ pub async fn http_request_to_df(req: RequestBuilder) -> Result<DataFrame> {
 /// Flatten nested columns without touching Utf8 JSON strings:
 /// - unnest all `Struct` columns
 /// - explode `List<Struct>` then unnest them
+///
 /// Repeats until no nested columns remain.
 pub fn normalize_unknown(df: &DataFrame) -> PolarsResult<DataFrame> {
     let mut out = df.clone();
@@ -266,3 +743,86 @@ pub fn normalize_unknown(df: &DataFrame) -> PolarsResult<DataFrame> {
 
     Ok(out)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use tokio::sync::mpsc;
+
+    use super::*;
+
+    fn ids(range: std::ops::Range<i64>) -> DataFrame {
+        df!("id" => range.collect::<Vec<_>>()).unwrap()
+    }
+
+    fn drain(mut rx: mpsc::Receiver<DataFrame>) -> Vec<DataFrame> {
+        let mut out = Vec::new();
+        while let Ok(df) = rx.try_recv() {
+            out.push(df);
+        }
+        out
+    }
+
+    #[test]
+    fn batches_rebatch_to_exact_sizes() {
+        let (tx, rx) = mpsc::channel(16);
+        let mut batches = Batches::new(100, tx);
+        for range in [0..30, 30..250, 250..260, 260..420] {
+            assert!(batches.push(ids(range)).unwrap());
+        }
+        batches.finish().unwrap();
+
+        let out = drain(rx);
+        let heights: Vec<_> = out.iter().map(DataFrame::height).collect();
+        assert_eq!(heights, vec![100, 100, 100, 100, 20]);
+        let all = concat_df_diagonal(&out).unwrap();
+        assert!(all.equals(&ids(0..420)));
+    }
+
+    #[test]
+    fn batches_stop_when_the_receiver_is_gone() {
+        let (tx, rx) = mpsc::channel(16);
+        drop(rx);
+        let mut batches = Batches::new(10, tx);
+        assert!(batches.push(ids(0..5)).unwrap());
+        assert!(!batches.push(ids(5..15)).unwrap());
+        batches.finish().unwrap();
+    }
+
+    #[test]
+    fn batches_send_nothing_for_empty_input() {
+        let (tx, rx) = mpsc::channel(16);
+        Batches::new(10, tx).finish().unwrap();
+        assert!(drain(rx).is_empty());
+    }
+
+    #[test]
+    fn ndjson_schema_comes_from_a_sample_beyond_the_first_batch() {
+        let path = std::env::temp_dir().join(format!("stream_{}.ndjson", uuid::Uuid::new_v4()));
+        let mut file = std::fs::File::create(&path).unwrap();
+        for i in 0..250 {
+            // `late` is null for the first batch; `amount` turns fractional.
+            let late = if i < 150 { "null".to_string() } else { i.to_string() };
+            let amount = if i < 200 { i.to_string() } else { format!("{i}.5") };
+            writeln!(file, r#"{{"id": {i}, "late": {late}, "amount": {amount}, "empty": null}}"#)
+                .unwrap();
+        }
+        drop(file);
+
+        let (tx, rx) = mpsc::channel(16);
+        let mut batches = Batches::new(100, tx);
+        stream_ndjson(&path, 100, &mut batches).unwrap();
+        batches.finish().unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let out = drain(rx);
+        assert_eq!(out.iter().map(DataFrame::height).collect::<Vec<_>>(), vec![100, 100, 50]);
+        for df in &out {
+            assert_eq!(df.column("late").unwrap().dtype(), &DataType::Int64);
+            assert_eq!(df.column("amount").unwrap().dtype(), &DataType::Float64);
+            assert_eq!(df.column("empty").unwrap().dtype(), &DataType::String);
+        }
+        assert_eq!(out[2].column("late").unwrap().i64().unwrap().get(0), Some(200));
+    }
+}
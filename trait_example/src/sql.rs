@@ -0,0 +1,103 @@
+use std::{borrow::Cow, sync::Arc};
+
+use futures_util::future::try_join_all;
+use polars::prelude::*;
+use polars::sql::SQLContext;
+
+use crate::errors::{Error, Result};
+use crate::sources::{SharedSource, Source};
+
+/// A pipeline stage that runs a SQL statement against the current frame.
+///
+/// The frame is registered as `input` unless renamed with
+/// [`table_name`](Self::table_name); extra frames and sources can be
+/// registered under their own names for joins.
+///
+/// In a streaming pipeline the statement runs once per batch, so it should
+/// be row-local: projections, filters and lookups joined from registered
+/// tables. `GROUP BY`, `DISTINCT` and window functions only see one batch.
+#[derive(Clone)]
+pub struct SqlTransform<'a> {
+    query: Cow<'a, str>,
+    table_name: Cow<'a, str>,
+    frames: Vec<(Cow<'a, str>, DataFrame)>,
+    sources: Vec<(Cow<'a, str>, SharedSource<'a>)>,
+}
+
+impl std::fmt::Debug for SqlTransform<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SqlTransform")
+            .field("query", &self.query)
+            .field("table_name", &self.table_name)
+            .field("frames", &self.frames.iter().map(|(n, _)| n).collect::<Vec<_>>())
+            .field("sources", &self.sources.iter().map(|(n, s)| (n, s.kind())).collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl<'a> SqlTransform<'a> {
+    pub fn new(query: impl Into<Cow<'a, str>>) -> Self {
+        Self {
+            query: query.into(),
+            table_name: Cow::Borrowed("input"),
+            frames: Vec::new(),
+            sources: Vec::new(),
+        }
+    }
+
+    /// Name the current frame is registered under; defaults to `input`.
+    pub fn table_name(mut self, name: impl Into<Cow<'a, str>>) -> Self {
+        self.table_name = name.into();
+        self
+    }
+
+    /// Register an in-memory frame under `name`.
+    pub fn frame(mut self, name: impl Into<Cow<'a, str>>, df: DataFrame) -> Self {
+        self.frames.push((name.into(), df));
+        self
+    }
+
+    /// Register a source under `name`; it is loaded once per pipeline run.
+    pub fn source(self, name: impl Into<Cow<'a, str>>, source: impl Source + Send + Sync + 'a) -> Self {
+        self.shared_source(name, Arc::new(source))
+    }
+
+    pub fn shared_source(mut self, name: impl Into<Cow<'a, str>>, source: SharedSource<'a>) -> Self {
+        self.sources.push((name.into(), source));
+        self
+    }
+
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    /// Load registered sources concurrently, then plan the statement.
+    pub async fn run(&self, lf: LazyFrame) -> Result<LazyFrame> {
+        self.load_sources().await?.execute(lf)
+    }
+
+    /// A copy with every registered source loaded into a frame, so running
+    /// it repeatedly, e.g. per streamed batch, doesn't fetch them again.
+    pub async fn load_sources(&self) -> Result<Self> {
+        let loaded = try_join_all(self.sources.iter().map(|(name, source)| async move {
+            let df = source.load_data().await?;
+            Ok::<_, Error>((name.clone(), df))
+        }))
+        .await?;
+
+        let mut transform = self.clone();
+        transform.sources.clear();
+        transform.frames.extend(loaded);
+        Ok(transform)
+    }
+
+    /// Plan the statement over frames only; sources must be loaded.
+    fn execute(&self, lf: LazyFrame) -> Result<LazyFrame> {
+        let mut ctx = SQLContext::new();
+        ctx.register(&self.table_name, lf);
+        for (name, frame) in &self.frames {
+            ctx.register(name, frame.clone().lazy());
+        }
+        Ok(ctx.execute(&self.query)?)
+    }
+}
@@ -0,0 +1,542 @@
+//! SQLite sink and source helpers, for tools without a database server and
+//! for testing pipelines in-process (`sqlite::memory:`).
+//!
+//! SQLite has no COPY, so rows are written as multi-row `INSERT`s with bound
+//! parameters inside one transaction per write.
+//!
+//! Every connection to `sqlite::memory:` is its own database, so share one
+//! pool between sink and source and keep it to a single, long-lived
+//! connection (`max_connections(1)`, no idle timeout or max lifetime).
+//! Streaming reads page through the query and hold the connection only while
+//! fetching a page, so a sink on the same pool can write in between.
+
+use polars::prelude::*;
+use serde::Deserialize;
+use serde_json::Value;
+use sqlx::{
+    sqlite::SqliteRow,
+    Column as _, Executor, Pool, QueryBuilder, Row, Sqlite, TypeInfo, ValueRef,
+};
+use tokio::sync::mpsc::Sender;
+
+use crate::errors::{Error, Result};
+
+/// How a write treats rows already in the table.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SqliteWriteMode {
+    #[default]
+    Append,
+    /// `INSERT ... ON CONFLICT (pk) DO UPDATE`; needs a primary key.
+    Upsert,
+    /// Delete every row first, in the same transaction as the insert.
+    Overwrite,
+}
+
+/// Double-quote an identifier and escape inner quotes.
+fn q(id: &str) -> String {
+    format!("\"{}\"", id.replace('"', "\"\""))
+}
+
+// ============================================================================
+// Data Type Mapping
+// ============================================================================
+
+/// Map a Polars `DataType` to an SQLite column type.
+///
+/// Temporal values are stored as ISO-8601 text and nested values as JSON
+/// text, which SQLite's date and JSON functions understand.
+pub fn polars_to_sqlite_dtype(dtype: &DataType) -> Result<String> {
+    use DataType::*;
+
+    let ty = match dtype {
+        Boolean | Int8 | Int16 | Int32 | Int64 | UInt8 | UInt16 | UInt32 | UInt64 => "INTEGER",
+        Float32 | Float64 => "REAL",
+        Binary => "BLOB",
+        Decimal(_, _) => "NUMERIC",
+        // Strings, categoricals, dates and times, lists and structs
+        _ => "TEXT",
+    };
+
+    Ok(ty.to_string())
+}
+
+// ============================================================================
+// Statements
+// ============================================================================
+
+/// The statements one SQLite write runs, in order. The insert is shown with
+/// one row of placeholders; writes repeat the row for each batch of rows.
+pub(crate) struct SqliteStatements {
+    create_table: Option<String>,
+    delete: Option<String>,
+    insert: String,
+    on_conflict: String,
+}
+
+impl SqliteStatements {
+    pub(crate) fn build(
+        df: &DataFrame,
+        table: &str,
+        auto_create: bool,
+        mode: SqliteWriteMode,
+        primary_key: Option<&str>,
+    ) -> Result<Self> {
+        let mut columns = Vec::with_capacity(df.width());
+        for column in df.get_columns() {
+            columns.push((q(column.name()), polars_to_sqlite_dtype(column.dtype())?));
+        }
+
+        let create_table = auto_create.then(|| {
+            let pk = primary_key
+                .map(|pk| format!(", PRIMARY KEY ({})", q(pk)))
+                .unwrap_or_default();
+            let cols = columns
+                .iter()
+                .map(|(name, ty)| format!("{name} {ty}"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!("CREATE TABLE IF NOT EXISTS {} ({cols}{pk})", q(table))
+        });
+
+        let delete = (mode == SqliteWriteMode::Overwrite).then(|| format!("DELETE FROM {}", q(table)));
+
+        let on_conflict = if mode == SqliteWriteMode::Upsert {
+            let pk = primary_key.ok_or_else(|| {
+                Error::Config("upsert requested but no primary key was provided".to_string())
+            })?;
+            let sets = df
+                .get_column_names()
+                .iter()
+                .filter(|c| c.as_str() != pk)
+                .map(|c| format!("{} = excluded.{}", q(c), q(c)))
+                .collect::<Vec<_>>();
+            if sets.is_empty() {
+                format!(" ON CONFLICT ({}) DO NOTHING", q(pk))
+            } else {
+                format!(" ON CONFLICT ({}) DO UPDATE SET {}", q(pk), sets.join(", "))
+            }
+        } else {
+            String::new()
+        };
+
+        let names = columns.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>();
+        Ok(Self {
+            create_table,
+            delete,
+            insert: format!("INSERT INTO {} ({}) ", q(table), names.join(", ")),
+            on_conflict,
+        })
+    }
+
+    pub(crate) fn into_vec(self, width: usize) -> Vec<String> {
+        let row = vec!["?"; width].join(", ");
+        let insert = format!("{}VALUES ({row}){}", self.insert, self.on_conflict);
+        [self.create_table, self.delete, Some(insert)]
+            .into_iter()
+            .flatten()
+            .collect()
+    }
+}
+
+// ============================================================================
+// Save Data to SQLite
+// ============================================================================
+
+/// SQLite allows 32766 bound parameters per statement.
+const MAX_PARAMS: usize = 32_766;
+
+pub(crate) async fn save_data_to_sqlite(
+    df: &DataFrame,
+    pool: &Pool<Sqlite>,
+    table: &str,
+    auto_create: bool,
+    mode: SqliteWriteMode,
+    primary_key: Option<&str>,
+) -> Result<()> {
+    let statements = SqliteStatements::build(df, table, auto_create, mode, primary_key)?;
+
+    let mut tx = pool.begin().await?;
+    if let Some(sql) = &statements.create_table {
+        sqlx::query(sql).execute(&mut *tx).await?;
+    }
+    if let Some(sql) = &statements.delete {
+        sqlx::query(sql).execute(&mut *tx).await?;
+    }
+
+    if df.width() > 0 {
+        let chunk = (MAX_PARAMS / df.width()).max(1);
+        for start in (0..df.height()).step_by(chunk) {
+            let rows = rows(&df.slice(start as i64, chunk))?;
+            let mut insert = QueryBuilder::<Sqlite>::new(&statements.insert);
+            insert.push_values(rows, |mut row, cells| {
+                for cell in cells {
+                    match cell {
+                        Cell::Null => row.push_bind(None::<i64>),
+                        Cell::Int(v) => row.push_bind(v),
+                        Cell::Real(v) => row.push_bind(v),
+                        Cell::Text(v) => row.push_bind(v),
+                        Cell::Blob(v) => row.push_bind(v),
+                    };
+                }
+            });
+            insert.push(&statements.on_conflict);
+            insert.build().execute(&mut *tx).await?;
+        }
+    }
+
+    tx.commit().await?;
+    Ok(())
+}
+
+/// One value in SQLite's storage classes.
+enum Cell {
+    Null,
+    Int(i64),
+    Real(f64),
+    Text(String),
+    Blob(Vec<u8>),
+}
+
+/// The frame's values row by row.
+fn rows(df: &DataFrame) -> Result<Vec<Vec<Cell>>> {
+    let mut rows: Vec<Vec<Cell>> = (0..df.height()).map(|_| Vec::with_capacity(df.width())).collect();
+    for column in df.get_columns() {
+        let series = match column.dtype() {
+            DataType::List(_) | DataType::Struct(_) | DataType::Boolean | DataType::String
+            | DataType::Binary => column.as_materialized_series().clone(),
+            dtype if dtype.is_primitive_numeric() => column.as_materialized_series().clone(),
+            // Dates, times, categoricals and decimals as their text form
+            _ => column.cast(&DataType::String)?.as_materialized_series().clone(),
+        };
+        for (row, value) in rows.iter_mut().zip(series.iter()) {
+            row.push(cell(value));
+        }
+    }
+    Ok(rows)
+}
+
+fn cell(value: AnyValue) -> Cell {
+    match value {
+        AnyValue::Null => Cell::Null,
+        AnyValue::Boolean(v) => Cell::Int(v.into()),
+        AnyValue::Int8(v) => Cell::Int(v.into()),
+        AnyValue::Int16(v) => Cell::Int(v.into()),
+        AnyValue::Int32(v) => Cell::Int(v.into()),
+        AnyValue::Int64(v) => Cell::Int(v),
+        AnyValue::UInt8(v) => Cell::Int(v.into()),
+        AnyValue::UInt16(v) => Cell::Int(v.into()),
+        AnyValue::UInt32(v) => Cell::Int(v.into()),
+        // Out of INTEGER range: keep the magnitude as REAL.
+        AnyValue::UInt64(v) => i64::try_from(v).map_or(Cell::Real(v as f64), Cell::Int),
+        AnyValue::Float32(v) => Cell::Real(v.into()),
+        AnyValue::Float64(v) => Cell::Real(v),
+        AnyValue::String(v) => Cell::Text(v.to_string()),
+        AnyValue::StringOwned(v) => Cell::Text(v.to_string()),
+        AnyValue::Binary(v) => Cell::Blob(v.to_vec()),
+        AnyValue::BinaryOwned(v) => Cell::Blob(v),
+        value @ (AnyValue::List(_) | AnyValue::Struct(..) | AnyValue::StructOwned(_)) => {
+            Cell::Text(to_json(value).to_string())
+        }
+        other => Cell::Text(other.to_string()),
+    }
+}
+
+/// A nested value as JSON, with the same leaf conversions as `cell`.
+pub(crate) fn to_json(value: AnyValue) -> Value {
+    match value.into_static() {
+        AnyValue::List(series) => Value::Array(series.iter().map(to_json).collect()),
+        AnyValue::StructOwned(payload) => {
+            let (values, fields) = *payload;
+            Value::Object(
+                fields
+                    .iter()
+                    .zip(values)
+                    .map(|(field, value)| (field.name.to_string(), to_json(value)))
+                    .collect(),
+            )
+        }
+        AnyValue::Boolean(v) => Value::Bool(v),
+        other => match cell(other) {
+            Cell::Null => Value::Null,
+            Cell::Int(v) => v.into(),
+            Cell::Real(v) => v.into(),
+            Cell::Text(v) => v.into(),
+            Cell::Blob(v) => String::from_utf8_lossy(&v).into_owned().into(),
+        },
+    }
+}
+
+// ============================================================================
+// Read from SQLite
+// ============================================================================
+
+/// Run `query` and collect the result into a frame.
+///
+/// SQLite types values rather than columns, so each column's dtype comes
+/// from the values it holds: integers as `Int64`, mixed integers and reals
+/// as `Float64`, anything with text as `String`, blobs as `Binary`. Booleans
+/// and dates come back as integers and text; cast them back or enforce a
+/// schema contract.
+pub(crate) async fn read_sqlite(pool: &Pool<Sqlite>, query: &str) -> Result<DataFrame> {
+    let rows = sqlx::query(query).fetch_all(pool).await?;
+    if rows.is_empty() {
+        // No rows to look at; still return the named columns.
+        let described = pool.describe(query).await?;
+        let columns = described
+            .columns()
+            .iter()
+            .map(|c| Column::full_null(c.name().into(), 0, &DataType::Null))
+            .collect();
+        return Ok(DataFrame::new(columns)?);
+    }
+    rows_to_frame(&rows)
+}
+
+/// Run `query` a page of `batch_size` rows at a time and send each page as a
+/// frame.
+///
+/// The connection goes back to the pool between pages rather than being held
+/// by an open cursor, which would starve a sink on a one-connection pool.
+/// Each page is a `LIMIT`/`OFFSET` over the query, so give it an `ORDER BY`
+/// for a stable order, and do not stream from a table the same run writes to.
+pub(crate) async fn stream_sqlite(
+    pool: &Pool<Sqlite>,
+    query: &str,
+    batch_size: usize,
+    tx: Sender<DataFrame>,
+) -> Result<()> {
+    let batch_size = batch_size.max(1);
+    let paged = format!(
+        "SELECT * FROM ({}) LIMIT ? OFFSET ?",
+        query.trim().trim_end_matches(';')
+    );
+    for offset in (0..).step_by(batch_size) {
+        let rows = sqlx::query(&paged)
+            .bind(batch_size as i64)
+            .bind(offset as i64)
+            .fetch_all(pool)
+            .await?;
+        let last = rows.len() < batch_size;
+        if !rows.is_empty() && tx.send(rows_to_frame(&rows)?).await.is_err() {
+            break;
+        }
+        if last {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn rows_to_frame(rows: &[SqliteRow]) -> Result<DataFrame> {
+    let Some(first) = rows.first() else {
+        return Ok(DataFrame::empty());
+    };
+    let mut columns = Vec::with_capacity(first.len());
+    for (i, column) in first.columns().iter().enumerate() {
+        let mut values = Vec::with_capacity(rows.len());
+        for row in rows {
+            values.push(any_value(row, i)?);
+        }
+        let dtype = column_dtype(&values);
+        let series = Series::from_any_values_and_dtype(column.name().into(), &values, &dtype, false)?;
+        columns.push(series.into_column());
+    }
+    Ok(DataFrame::new(columns)?)
+}
+
+/// Decode by the value's storage class, not the column's declared type.
+fn any_value(row: &SqliteRow, i: usize) -> Result<AnyValue<'static>> {
+    let raw = row.try_get_raw(i)?;
+    if raw.is_null() {
+        return Ok(AnyValue::Null);
+    }
+    let class = raw.type_info().name().to_string();
+    Ok(match class.as_str() {
+        "INTEGER" => AnyValue::Int64(row.try_get(i)?),
+        "REAL" => AnyValue::Float64(row.try_get(i)?),
+        "BLOB" => AnyValue::BinaryOwned(row.try_get(i)?),
+        _ => AnyValue::StringOwned(row.try_get::<String, _>(i)?.into()),
+    })
+}
+
+/// The narrowest dtype holding every value of a column.
+fn column_dtype(values: &[AnyValue]) -> DataType {
+    let mut dtype = DataType::Null;
+    for value in values {
+        dtype = match (&dtype, value) {
+            (_, AnyValue::Null) => dtype,
+            (DataType::String, _) | (_, AnyValue::StringOwned(_)) => DataType::String,
+            (DataType::Binary, _) | (_, AnyValue::BinaryOwned(_)) => DataType::Binary,
+            (DataType::Float64, _) | (_, AnyValue::Float64(_)) => DataType::Float64,
+            _ => DataType::Int64,
+        };
+    }
+    dtype
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use sqlx::sqlite::SqlitePoolOptions;
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::pipelines::Pipeline;
+    use crate::sinks::{Sink, Sinker};
+    use crate::sources::SourceKind;
+
+    /// One long-lived connection, so every query sees the same database.
+    async fn memory_pool() -> Arc<Pool<Sqlite>> {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .acquire_timeout(Duration::from_secs(5))
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        Arc::new(pool)
+    }
+
+    async fn read(pool: &Pool<Sqlite>, query: &str) -> DataFrame {
+        read_sqlite(pool, query).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn append_upsert_and_overwrite() {
+        let pool = memory_pool().await;
+        let first = df!("id" => [1i64, 2], "name" => ["a", "b"]).unwrap();
+        let second = df!("id" => [2i64, 3], "name" => ["B", "c"]).unwrap();
+        let save = |df: DataFrame, mode| {
+            let pool = pool.clone();
+            async move {
+                save_data_to_sqlite(&df, &pool, "t", true, mode, Some("id")).await.unwrap();
+            }
+        };
+
+        save(first.clone(), SqliteWriteMode::Append).await;
+        let err = save_data_to_sqlite(&first, &pool, "t", true, SqliteWriteMode::Append, Some("id"))
+            .await;
+        assert!(err.is_err(), "appending existing keys violates the primary key");
+
+        save(second.clone(), SqliteWriteMode::Upsert).await;
+        let df = read(&pool, "SELECT id, name FROM t ORDER BY id").await;
+        let expected = df!("id" => [1i64, 2, 3], "name" => ["a", "B", "c"]).unwrap();
+        assert!(df.equals(&expected), "{df}");
+
+        save(second.clone(), SqliteWriteMode::Overwrite).await;
+        let df = read(&pool, "SELECT id, name FROM t ORDER BY id").await;
+        assert!(df.equals(&second), "{df}");
+    }
+
+    #[tokio::test]
+    async fn upsert_needs_a_primary_key() {
+        let pool = memory_pool().await;
+        let df = df!("id" => [1i64]).unwrap();
+        let result = save_data_to_sqlite(&df, &pool, "t", true, SqliteWriteMode::Upsert, None).await;
+        assert!(matches!(result, Err(Error::Config(_))));
+    }
+
+    #[tokio::test]
+    async fn overwrite_stream_replaces_once_then_appends() {
+        let pool = memory_pool().await;
+        let old = df!("id" => [7i64, 8, 9]).unwrap();
+        save_data_to_sqlite(&old, &pool, "t", true, SqliteWriteMode::Append, None).await.unwrap();
+
+        let sink = Sinker::sqlite(pool.clone(), "t", true, SqliteWriteMode::Overwrite);
+        let (tx, rx) = mpsc::channel(2);
+        tx.send(df!("id" => [1i64, 2]).unwrap()).await.unwrap();
+        tx.send(df!("id" => [3i64]).unwrap()).await.unwrap();
+        drop(tx);
+        assert_eq!(sink.save_stream(rx).await.unwrap(), 3);
+
+        let df = read(&pool, "SELECT id FROM t ORDER BY id").await;
+        assert!(df.equals(&df!("id" => [1i64, 2, 3]).unwrap()), "{df}");
+    }
+
+    #[tokio::test]
+    async fn values_round_trip_by_storage_class() {
+        let pool = memory_pool().await;
+        let list = Series::new("".into(), [1i64, 2]);
+        let df = df!(
+            "int" => [Some(1i64), None],
+            "real" => [1.5f64, -2.0],
+            "text" => [Some("x"), None],
+            "flag" => [true, false],
+            "blob" => [b"ab".as_slice(), b"".as_slice()],
+            "mixed" => [1.0f64, 2.5],
+        )
+        .unwrap()
+        .lazy()
+        .with_columns([
+            lit("2024-05-01").str().to_date(Default::default()).alias("day"),
+            lit(Scalar::new(DataType::List(Box::new(DataType::Int64)), AnyValue::List(list)))
+                .alias("list"),
+        ])
+        .collect()
+        .unwrap();
+        save_data_to_sqlite(&df, &pool, "t", true, SqliteWriteMode::Append, None).await.unwrap();
+
+        let back = read(&pool, "SELECT * FROM t").await;
+        let dtypes: Vec<_> = back.dtypes();
+        assert_eq!(
+            dtypes,
+            [
+                DataType::Int64,
+                DataType::Float64,
+                DataType::String,
+                DataType::Int64,
+                DataType::Binary,
+                DataType::Float64,
+                DataType::String,
+                DataType::String,
+            ]
+        );
+        let row = back.get(0).unwrap();
+        assert_eq!(row[0], AnyValue::Int64(1));
+        assert_eq!(row[3], AnyValue::Int64(1));
+        assert_eq!(row[6], AnyValue::String("2024-05-01"));
+        assert_eq!(row[7], AnyValue::String("[1,2]"));
+        let row = back.get(1).unwrap();
+        assert_eq!(row[0], AnyValue::Null);
+        assert_eq!(row[2], AnyValue::Null);
+        assert_eq!(row[4], AnyValue::Binary(b""));
+        assert_eq!(row[5], AnyValue::Float64(2.5));
+    }
+
+    #[tokio::test]
+    async fn empty_result_keeps_column_names() {
+        let pool = memory_pool().await;
+        sqlx::query("CREATE TABLE t (id INTEGER, name TEXT)").execute(&*pool).await.unwrap();
+
+        let df = read(&pool, "SELECT id, name AS label FROM t").await;
+        assert_eq!(df.height(), 0);
+        assert_eq!(df.get_column_names(), ["id", "label"]);
+    }
+
+    #[tokio::test]
+    async fn streams_between_tables_on_one_connection() {
+        let pool = memory_pool().await;
+        let df = df!("id" => (0..1_000i64).collect::<Vec<_>>()).unwrap();
+        save_data_to_sqlite(&df, &pool, "a", true, SqliteWriteMode::Append, None).await.unwrap();
+
+        let pipeline = Pipeline::builder()
+            .name("copy")
+            .source(SourceKind::read_sqlite(pool.clone(), "SELECT id FROM a ORDER BY id;"))
+            .sink(Sinker::sqlite(pool.clone(), "b", true, SqliteWriteMode::Append))
+            .streaming(100)
+            .channel_capacity(1)
+            .build()
+            .unwrap();
+        let report = tokio::time::timeout(Duration::from_secs(30), pipeline.run())
+            .await
+            .expect("source and sink share the connection")
+            .unwrap();
+        assert_eq!(report.rows_out, 1_000);
+
+        let copied = read(&pool, "SELECT id FROM b ORDER BY id").await;
+        assert!(copied.equals(&df));
+    }
+}
@@ -0,0 +1,151 @@
+use std::{
+    fmt,
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::PathBuf,
+    sync::Mutex,
+    time::UNIX_EPOCH,
+};
+
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{
+    error::{OTelSdkError, OTelSdkResult},
+    trace::{SdkTracerProvider, SpanData, SpanExporter},
+    Resource,
+};
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+
+use crate::errors::{Error, Result};
+
+// ============================================================================
+// Config
+// ============================================================================
+
+/// Where finished spans are sent.
+#[derive(Clone, Debug)]
+pub enum TraceExport {
+    /// OTLP over HTTP/protobuf, e.g. `http://localhost:4318/v1/traces`.
+    Otlp { endpoint: String },
+    /// One JSON object per span on stdout.
+    Stdout,
+    /// One JSON object per span appended to a file.
+    File(PathBuf),
+}
+
+/// Flushes and shuts the tracer provider down when dropped.
+pub struct TelemetryGuard {
+    provider: SdkTracerProvider,
+}
+
+impl Drop for TelemetryGuard {
+    fn drop(&mut self) {
+        if let Err(e) = self.provider.shutdown() {
+            eprintln!("failed to shut down tracer provider: {e}");
+        }
+    }
+}
+
+/// Install a `fmt` subscriber plus an OpenTelemetry layer exporting to `export`.
+///
+/// Keep the returned guard alive for the lifetime of the process.
+pub fn init(service_name: &str, export: TraceExport) -> Result<TelemetryGuard> {
+    let resource = Resource::builder()
+        .with_service_name(service_name.to_string())
+        .build();
+    let builder = SdkTracerProvider::builder().with_resource(resource);
+
+    let provider = match export {
+        TraceExport::Otlp { endpoint } => {
+            let exporter = opentelemetry_otlp::SpanExporter::builder()
+                .with_http()
+                .with_endpoint(endpoint)
+                .build()
+                .map_err(|e| Error::Telemetry(e.to_string()))?;
+            builder.with_batch_exporter(exporter).build()
+        }
+        TraceExport::Stdout => builder
+            .with_simple_exporter(JsonLinesExporter::new(io::stdout()))
+            .build(),
+        TraceExport::File(path) => {
+            let file = OpenOptions::new().create(true).append(true).open(path)?;
+            builder
+                .with_simple_exporter(JsonLinesExporter::<File>::new(file))
+                .build()
+        }
+    };
+
+    let tracer = provider.tracer(service_name.to_string());
+    tracing_subscriber::registry()
+        .with(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")))
+        .with(tracing_subscriber::fmt::layer())
+        .with(tracing_opentelemetry::layer().with_tracer(tracer))
+        .try_init()
+        .map_err(|e| Error::Telemetry(e.to_string()))?;
+
+    Ok(TelemetryGuard { provider })
+}
+
+// ============================================================================
+// JSON lines exporter
+// ============================================================================
+
+/// Span exporter that writes one JSON object per span; for use without a collector.
+pub struct JsonLinesExporter<W> {
+    writer: Mutex<W>,
+}
+
+impl<W: Write + Send> JsonLinesExporter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer: Mutex::new(writer),
+        }
+    }
+}
+
+impl<W> fmt::Debug for JsonLinesExporter<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JsonLinesExporter").finish_non_exhaustive()
+    }
+}
+
+fn span_to_json(span: &SpanData) -> serde_json::Value {
+    let nanos = |t: std::time::SystemTime| {
+        t.duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or_default()
+    };
+    let attributes: serde_json::Map<String, serde_json::Value> = span
+        .attributes
+        .iter()
+        .map(|kv| (kv.key.to_string(), serde_json::Value::String(kv.value.to_string())))
+        .collect();
+
+    serde_json::json!({
+        "name": span.name,
+        "trace_id": span.span_context.trace_id().to_string(),
+        "span_id": span.span_context.span_id().to_string(),
+        "parent_span_id": span.parent_span_id.to_string(),
+        "start_time_unix_nano": nanos(span.start_time),
+        "end_time_unix_nano": nanos(span.end_time),
+        "status": format!("{:?}", span.status),
+        "attributes": attributes,
+    })
+}
+
+impl<W: Write + Send> SpanExporter for JsonLinesExporter<W> {
+    async fn export(&self, batch: Vec<SpanData>) -> OTelSdkResult {
+        let mut writer = self
+            .writer
+            .lock()
+            .map_err(|e| OTelSdkError::InternalFailure(e.to_string()))?;
+        for span in &batch {
+            serde_json::to_writer(&mut *writer, &span_to_json(span))
+                .map_err(|e| OTelSdkError::InternalFailure(e.to_string()))?;
+            writeln!(writer).map_err(|e| OTelSdkError::InternalFailure(e.to_string()))?;
+        }
+        writer
+            .flush()
+            .map_err(|e| OTelSdkError::InternalFailure(e.to_string()))
+    }
+}
@@ -9,3 +9,13 @@ macro_rules! impl_from_error {
         })*
     };
 }
+
+/// Collect a lazy plan on the blocking pool.
+///
+/// Polars drives file scans on its own runtime, which must not be entered
+/// from a Tokio worker thread.
+pub async fn collect_lazy(
+    lf: polars::prelude::LazyFrame,
+) -> crate::errors::Result<polars::frame::DataFrame> {
+    Ok(tokio::task::spawn_blocking(move || lf.collect()).await??)
+}